@@ -8,7 +8,7 @@ from django.http.request import HttpRequest
 
 from .models import PgcronnerJobs
 
-from pgcronner import Job
+from pgcronner import Job, Schedule
 
 logger = logging.getLogger(__name__)
 
@@ -38,13 +38,41 @@ def validate_schedule(value: str) -> None:
     """
     if not value:
         raise forms.ValidationError("Schedule cannot be empty")
+    try:
+        Schedule(value)
+    except ValueError as e:
+        raise forms.ValidationError(str(e)) from e
+
+
+def to_schedule(value: str) -> str:
+    """Reads a cron schedule, or a simple English one such as "every day at 3am".
+
+    Args:
+        value (str): The value of the schedule field.
+
+    Returns:
+        str: The pg_cron schedule.
+    """
+    try:
+        return Schedule(value).expression
+    except ValueError:
+        try:
+            return Schedule.from_text(value).expression
+        except ValueError:
+            return value
 
 
 class PgCronnerJobsForm(forms.ModelForm):
     """Form for the PgCronnerJobs model."""
 
     command = forms.CharField(widget=forms.Textarea, validators=[validate_command])
-    schedule = forms.CharField(validators=[validate_schedule])
+    schedule = forms.CharField(help_text='A cron schedule, or plain English such as "every day at 3am"')
+
+    def clean_schedule(self) -> str:
+        """Turns plain English schedules into cron schedules, then validates them."""
+        schedule = to_schedule(self.cleaned_data["schedule"])
+        validate_schedule(schedule)
+        return schedule
 
     class Meta:
         """Meta class."""
@@ -59,11 +87,19 @@ class PgCronnerJobsAdmin(admin.ModelAdmin):
 
     form = PgCronnerJobsForm
 
-    list_display = ("name", "command", "schedule", "last_run")
+    list_display = ("name", "command", "schedule", "schedule_description", "last_run")
     search_fields = ("name", "command", "schedule")
     list_filter = ("name", "command", "schedule", "active")
     ordering = ("last_run", "name", "command", "schedule")
 
+    @admin.display(description="Runs")
+    def schedule_description(self, obj: PgcronnerJobs) -> str:
+        """The schedule in plain English."""
+        try:
+            return Schedule(str(obj.schedule)).describe()
+        except ValueError:
+            return ""
+
     def save_model(self, request: HttpRequest, obj: PgcronnerJobs, form: forms.Form, change):
         """Override save_model to add the job using pgcronner.
 
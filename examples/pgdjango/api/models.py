@@ -8,7 +8,7 @@ from django.utils import timezone
 
 from .services import handle_cronjobs_pre_save
 
-from pgcronner import Job
+from pgcronner import Job, PgCronnerError
 
 logger = logging.getLogger(__name__)
 pgcron = getattr(settings, "PGCRON", None)
@@ -24,7 +24,7 @@ class PgcronnerManager(models.Manager):
         """Overrides default get method to add additional functionality."""
         try:
             pgcron.refresh()
-        except OSError as e:
+        except PgCronnerError as e:
             logger.error("Error refreshing cronjobs: %s", e)
 
         return super(PgcronnerManager, self).get()
@@ -34,7 +34,7 @@ class PgcronnerManager(models.Manager):
 
         try:
             pgcron.refresh()
-        except OSError as e:
+        except PgCronnerError as e:
             logger.error("Error refreshing cronjobs: %s", e)
 
         return super(PgcronnerManager, self).get_queryset()
@@ -53,7 +53,7 @@ class PgcronnerManager(models.Manager):
                     source=kwargs["source"],
                 )
             )
-        except OSError as e:
+        except PgCronnerError as e:
             logger.error("Error creating cronjobs: %s", e)
 
         pgcron.sync()
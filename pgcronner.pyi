@@ -1,14 +1,130 @@
-from typing import Optional, List, Dict
+from typing import Optional, List, Dict, Tuple, Union
+import builtins
 import datetime
 
+class PgCronnerError(Exception):
+    """
+    Base class of the errors raised by pgcronner
+
+    :param sqlstate: SQLSTATE code of the server's error, None if the error didn't come from it
+    :param job_name: the job the error is about, None if it isn't about one job
+    """
+    sqlstate: Optional[str]
+    job_name: Optional[str]
+
+class DatabaseError(PgCronnerError, OSError):
+    """A query failed"""
+
+class ConnectionError(DatabaseError, builtins.ConnectionError):
+    """Could not connect to the database, or the connection was lost"""
+
+class JobNotFound(DatabaseError):
+    """No job has this name"""
+
+class JobAlreadyExists(DatabaseError):
+    """A job with this name was already added"""
+
+class PgCronNotInstalled(DatabaseError):
+    """The pg_cron extension is missing, or too old for a job"""
+
+class ValidationError(PgCronnerError, ValueError):
+    """A job or an argument is invalid"""
+
+class InvalidSchedule(ValidationError):
+    """A schedule pg_cron can't run"""
+
+class Schedule(object):
+    """
+    A pg_cron schedule, validated when it is created
+
+    :param expression: 5 cron fields, a macro such as @daily or an interval such as "30 seconds"
+
+    :throws: ValueError
+    """
+    expression: str
+
+    def __init__(self, expression: str) -> None: ...
+
+    @staticmethod
+    def from_text(text: str) -> "Schedule":
+        """
+        Read a simple English schedule, e.g. "every day at 3am", "every 15 minutes",
+        "every weekday at 09:30", "every month on the last day" or "at startup"
+
+        :throws: ValueError if the text can't be read
+        """
+
+    @staticmethod
+    def cron(expression: str) -> "Schedule":
+        """
+        Schedule in pg_cron syntax, same as ``Schedule(expression)``
+
+        :throws: ValueError
+        """
+
+    @staticmethod
+    def every_seconds(seconds: int) -> "Schedule":
+        """
+        Every 1 to 59 seconds, needs pg_cron 1.5 or newer
+
+        :throws: ValueError
+        """
+
+    @staticmethod
+    def every_minutes(minutes: int) -> "Schedule":
+        """
+        Every 1 to 59 minutes from the start of the hour, e.g. every_minutes(5) is */5 * * * *
+
+        :throws: ValueError
+        """
+
+    @staticmethod
+    def every_hours(hours: int) -> "Schedule":
+        """
+        Every 1 to 23 hours from midnight, on the hour
+
+        :throws: ValueError
+        """
+
+    @staticmethod
+    def daily(at: str = "00:00") -> "Schedule":
+        """
+        Every day at a time such as "03:00", "3am" or "noon"
+
+        :throws: ValueError
+        """
+
+    @staticmethod
+    def weekly(days: List[Union[str, int]], at: str = "00:00") -> "Schedule":
+        """
+        Every week on some days at a time
+
+        :param days: names such as "mon" or "Monday", or numbers where 0 and 7 are Sunday
+        :param at: time such as "03:00", "3am" or "noon"
+
+        :throws: ValueError
+        """
+
+    def describe(self) -> str:
+        """
+        The schedule in plain English, e.g. "at 03:00 every day"
+        """
+
 class Job(object):
     """
     Job object
     
     :param name: job name
-    :param schedule: cron schedule
+    :param schedule: Schedule, or pg_cron schedule: 5 cron fields ($ is the last day of the month), a macro
+        such as @daily or @reboot, or an interval of 1 to 59 seconds such as "30 seconds".
+        Cron fields can use H, H(a-b), H/n or H(a-b)/n for a value picked from a hash of the name
     :param command: command to run
     :param source: source of function if command is a function call e.g. "CALL f();"
+    :param timezone: timezone the schedule is written in e.g. "Europe/Berlin", by default the
+        server's cron.timezone. Sync translates the schedule to cron.timezone, or skips the job
+        if no schedule there gives exactly the same runs
+
+    Set ``active`` to False to disable or unschedule the job on sync.
 
     """
     name: str
@@ -17,18 +133,163 @@ class Job(object):
     source: str
     last_run: Optional[datetime.datetime]
     active: bool
+    timezone: Optional[str]
+    next_run: Optional[datetime.datetime]
+    """next run in the job's timezone, UTC if it has none, see next_runs"""
+    expanded_schedule: str
+    """schedule with H tokens replaced by the values picked from the job name, as pg_cron gets it"""
+    schedule_kind: str
+    """one of cron, interval (every n seconds) or reboot"""
+    interval: Optional[datetime.timedelta]
+    """time between runs of an interval schedule, None for other schedules"""
+
+    def __init__(
+        self,
+        name: str,
+        schedule: Union[str, Schedule],
+        command: str,
+        source: str,
+        timezone: Optional[str] = None,
+    ) -> None: ...
+
+    def describe_schedule(self) -> str:
+        """
+        The schedule in plain English, followed by the job's timezone if it has one, e.g.
+        "every 15 minutes between 09:00 and 17:59, Monday through Friday"
+
+        :throws: ValueError
+        """
+
+    def next_runs(
+        self, n: int, after: Optional[datetime.datetime] = None
+    ) -> List[datetime.datetime]:
+        """
+        Next times the schedule fires, as aware datetimes in the job's timezone. Without a
+        connection the server's cron.timezone isn't known, a job without a timezone is computed
+        in UTC, use PgCronner.next_runs for it. Interval schedules are counted from the last run
+        of the job, if it ran
+
+        :param n: number of runs
+        :param after: only runs after this datetime, defaults to now
+        :return: list of datetimes, shorter if the schedule stops firing e.g. @reboot
+
+        :throws: ValueError
+        """
+
+class PlanAction(object):
+    """
+    A change sync would make
+
+    :param kind: one of create, update_schedule, update_command, recreate_procedure, disable,
+        enable, unschedule, drop_procedure
+    :param job: job name, or stored procedure name for drop_procedure
+    :param before: value before the sync, if any
+    :param after: value after the sync, if any
+    """
+    kind: str
+    job: str
+    before: Optional[str]
+    after: Optional[str]
+
+class SyncPlan(object):
+    """
+    Actions sync would take, str() renders them as a diff
+    """
+    actions: List[PlanAction]
+
+    def is_empty(self) -> bool: ...
 
-    def __init__(self, name: str, schedule: str, command: str, source: str) -> None: ...
+    def __len__(self) -> int: ...
+
+class JobRun(object):
+    """
+    A run of a job, from cron.job_run_details
+
+    :param runid: id of the run
+    :param jobid: id of the job in cron.job at the time of the run
+    :param job: job name
+    :param pid: backend pid that ran the command
+    :param status: one of starting, running, sending, connecting, succeeded, failed
+    :param return_message: command tag or error message
+    :param start_time: when the run started, in UTC
+    :param end_time: when the run ended, None while it is running
+    :param duration: duration of the run in seconds, None while it is running
+    """
+    runid: int
+    jobid: int
+    job: str
+    pid: Optional[int]
+    status: str
+    return_message: Optional[str]
+    start_time: Optional[datetime.datetime]
+    end_time: Optional[datetime.datetime]
+    duration: Optional[float]
+
+class JobSyncResult(object):
+    """
+    Outcome of a sync for a single job
+
+    :param job: job name, or stored procedure name for procedure_dropped
+    :param status: one of scheduled, updated, unchanged, disabled, unscheduled, procedure_dropped,
+        skipped_invalid, procedure_failed, schedule_failed
+    :param error: underlying error message if the job failed
+    """
+    job: str
+    status: str
+    error: Optional[str]
+
+class SyncReport(object):
+    """
+    What a sync did for each job
+    """
+    jobs: List[JobSyncResult]
+    total: int
+    scheduled: int
+    unscheduled: int
+    failed: int
+    elapsed: float
+
+    def failures(self) -> List[JobSyncResult]: ...
 
 class PgCronner(object):
     """
     PgCronner object
 
     :param db_uri: database uri, if not set tries to use DATABASE_URL env var
-    :param table_name: table name to store jobs in
+    :param table_name: table name to store jobs in, quoted as is so case is kept
+    :param inactive: what sync does with inactive jobs, "disable" keeps them in cron.job
+        with active = false, "unschedule" removes them
+    :param namespace: prefix jobs and procedures with "pgcronner_<namespace>__" so sync and clear
+        only touch this instance's objects, by default jobs are prefixed with "pgcronner__"
+    :param schema: schema of the table, created if it doesn't exist, by default the search path
+        decides
+    :param sslrootcert: CA bundle the server certificate is checked against, TLS is driven by the
+        sslmode of db_uri (disable, prefer, require, verify-ca, verify-full)
+    :param sslcert: client certificate
+    :param sslkey: private key of the client certificate
+    :param retention_days: runs copied to "<table_name>_runs" are deleted after this many days,
+        by default they are kept forever
+    :param pool_size: maximum number of connections, opened as they are needed. Threads can share
+        one PgCronner, lost connections are replaced and reads are retried once on a new one
+    :param lazy: don't connect, or create the table, until the first call. Either way a forked
+        process, e.g. a gunicorn worker, opens its own connections
     """
+    pool_size: int
 
-    def __init__(self, db_uri: str = "", table_name: str = "pgcronner_jobs"): ...
+    def __init__(
+        self,
+        db_uri: str = "",
+        table_name: str = "pgcronner_jobs",
+        inactive: str = "disable",
+        namespace: Optional[str] = None,
+        schema: Optional[str] = None,
+        sslrootcert: Optional[str] = None,
+        sslcert: Optional[str] = None,
+        sslkey: Optional[str] = None,
+        retention_days: Optional[int] = None,
+        pool_size: int = 4,
+        lazy: bool = False,
+    ): ...
 
     def __str__(self) -> str: ...
 
@@ -81,9 +342,20 @@ class PgCronner(object):
         :throws: OsError, ValueError
         """
 
+    def check_index(self, create: bool = False) -> Optional[str]:
+        """
+        Checks that cron.job_run_details has an index on (jobid, start_time), which keeps
+        reading the last run of jobs fast as the run history grows
+
+        :param create: create the index if it is missing, needs ownership of cron.job_run_details
+        :return: None if the index exists, otherwise the statement creating it
+
+        :throws: OsError
+        """
+
     def clear(self) -> bool:
         """
-        Clears all jobs from the table and crontab
+        Clears all jobs in the namespace from the table and crontab
         
         :return: True if successful
         
@@ -91,12 +363,220 @@ class PgCronner(object):
         """
 
 
-    def sync(self) -> bool:
+    def sync(self, atomic: bool = False) -> SyncReport:
         """
-        Syncs jobs from the table to crontab creating functions if necessary
+        Syncs jobs from the table to crontab creating functions if necessary.
+        Only new, changed or removed jobs are touched, unchanged jobs keep their jobid.
+        Runs in a single transaction. Fails if a job has an interval schedule and pg_cron is
+        older than 1.5.
+
+        :param atomic: roll back the whole sync if any job fails, otherwise failing jobs are skipped
         
+        :return: SyncReport with the outcome of every job
+
+        :throws: OsError, ValueError
+        """
+
+    def plan(self) -> SyncPlan:
+        """
+        Computes what sync would do without changing anything
+
+        :return: SyncPlan
+
+        :throws: OsError, ValueError
+        """
+
+    def orphans(self, drop: bool = False) -> List[str]:
+        """
+        Lists stored procedures in this namespace that no job references
+
+        :param drop: drop the orphaned procedures
+        :return: names of the orphaned procedures
+
+        :throws: OsError
+        """
+
+    def history(
+        self,
+        jobname: str,
+        limit: int = 100,
+        offset: int = 0,
+        since: Optional[datetime.datetime] = None,
+        status: Optional[str] = None,
+    ) -> List[JobRun]:
+        """
+        Run history of a job in cron.job_run_details, most recent first. Runs are matched to
+        the job by its jobid in cron.job, once the job is unscheduled use archived_runs
+
+        :param jobname: job name
+        :param limit: maximum number of runs
+        :param offset: number of runs to skip, for pagination
+        :param since: only runs started at or after this datetime
+        :param status: only runs with this status
+        :return: list of JobRun
+
+        :throws: OsError, ValueError
+        """
+
+    def archived_runs(
+        self,
+        jobname: str,
+        limit: int = 100,
+        offset: int = 0,
+        since: Optional[datetime.datetime] = None,
+        status: Optional[str] = None,
+    ) -> List[JobRun]:
+        """
+        Runs of a job copied to "<table_name>_runs", most recent first. Runs are copied on sync,
+        refresh, clear and archive, and survive the job being recreated or pg_cron purging its
+        own history
+
+        :param jobname: job name
+        :param limit: maximum number of runs
+        :param offset: number of runs to skip, for pagination
+        :param since: only runs started at or after this datetime
+        :param status: only runs with this status
+        :return: list of JobRun
+
+        :throws: OsError, ValueError
+        """
+
+    def archive(self) -> int:
+        """
+        Copies new runs from cron.job_run_details to "<table_name>_runs" and deletes the ones
+        past the retention
+
+        :return: number of runs copied or updated
+
+        :throws: OsError
+        """
+
+    def upcoming(
+        self,
+        window: datetime.timedelta,
+        start: Optional[datetime.datetime] = None,
+        limit: int = 500,
+    ) -> List[Tuple[datetime.datetime, Job]]:
+        """
+        Lists every run of the active jobs within a time window, sorted by time. Jobs without a
+        timezone run in the server's cron.timezone
+
+        :param window: length of the window
+        :param start: start of the window, defaults to now
+        :param limit: only the first runs, e.g. a "1 second" job fires 3600 times an hour
+        :return: list of (datetime, Job) tuples
+
+        :throws: OsError, ValueError
+        """
+
+    def next_runs(
+        self, jobname: str, n: int = 5
+    ) -> List[Tuple[datetime.datetime, datetime.datetime]]:
+        """
+        Next runs of a job, in the job's timezone and in the server's cron.timezone. A job
+        without a timezone runs in cron.timezone
+
+        :param jobname: job name
+        :param n: number of runs
+        :return: list of (job time, server time) tuples
+
+        :throws: OsError, ValueError
+        """
+
+    def activate(self, jobname: str) -> bool:
+        """
+        Activates a job, taking effect right away without a full sync
+
+        :param jobname: job name
         :return: True if successful
 
         :throws: OsError, ValueError
         """
 
+    def deactivate(self, jobname: str) -> bool:
+        """
+        Deactivates a job, disabling or unscheduling it right away depending on the inactive policy
+
+        :param jobname: job name
+        :return: True if successful
+
+        :throws: OsError, ValueError
+        """
+
+class AsyncPgCronner(object):
+    """
+    PgCronner for asyncio applications, every method returns an awaitable.
+    Calls run the job and sync code of PgCronner on tokio-postgres connections, so the event loop
+    isn't blocked while they wait on Postgres. Nothing connects, and the table isn't created,
+    until the first call.
+
+    Takes the parameters of PgCronner except lazy.
+    """
+    pool_size: int
+
+    def __init__(
+        self,
+        db_uri: str = "",
+        table_name: str = "pgcronner_jobs",
+        inactive: str = "disable",
+        namespace: Optional[str] = None,
+        schema: Optional[str] = None,
+        sslrootcert: Optional[str] = None,
+        sslcert: Optional[str] = None,
+        sslkey: Optional[str] = None,
+        retention_days: Optional[int] = None,
+        pool_size: int = 4,
+    ): ...
+
+    def __str__(self) -> str: ...
+
+    def __repr__(self) -> str: ...
+
+    async def all(self) -> List[Job]:
+        """
+        Get all jobs in the table, see PgCronner.all
+
+        :throws: OSError, ValueError
+        """
+
+    async def one(self, jobname: str) -> Job:
+        """
+        Get one job by name, see PgCronner.one
+
+        :throws: OSError, ValueError
+        """
+
+    async def add(self, job: Job) -> bool:
+        """
+        Add a job to the table, see PgCronner.add
+
+        :throws: OSError, ValueError
+        """
+
+    async def remove(self, jobname: str) -> bool:
+        """
+        Remove a job from the table, see PgCronner.remove
+
+        :throws: OSError, ValueError
+        """
+
+    async def sync(self, atomic: bool = False) -> SyncReport:
+        """
+        Sync the table with cron.job, see PgCronner.sync
+
+        :throws: OSError, ValueError
+        """
+
+    async def refresh(self) -> bool:
+        """
+        Update the last run of all jobs, see PgCronner.refresh
+
+        :throws: OSError, ValueError
+        """
+
+    async def clear(self) -> bool:
+        """
+        Remove all jobs of the namespace, see PgCronner.clear
+
+        :throws: OSError, ValueError
+        """
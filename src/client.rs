@@ -0,0 +1,184 @@
+//! Database client
+//! The job and sync logic runs its queries through `Client`, implemented by the blocking clients
+//! of `postgres` used by PgCronner and by the async ones of `tokio_postgres` used by
+//! AsyncPgCronner, so both classes share a single implementation of it.
+//!
+//! The logic is written as async functions that only await `Client` calls. PgCronner runs them
+//! with `block_on` on the calling thread: a blocking client answers before its future is
+//! returned, so nothing waits.
+
+use postgres::types::ToSql;
+use postgres::{Error, Row};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+/// The queries pgcronner runs, on a blocking or an async client
+pub trait Client: Send {
+    fn query(
+        &mut self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<Vec<Row>, Error>> + Send;
+
+    fn query_one(
+        &mut self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<Row, Error>> + Send;
+
+    fn query_opt(
+        &mut self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<Option<Row>, Error>> + Send;
+
+    fn execute(
+        &mut self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<u64, Error>> + Send;
+
+    fn batch_execute(&mut self, query: &str) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Start a transaction, or a savepoint in a transaction, rolled back when dropped
+    fn transaction(&mut self) -> impl Future<Output = Result<impl Transaction + '_, Error>> + Send;
+}
+
+/// A transaction started by `Client::transaction`
+pub trait Transaction: Client {
+    fn commit(self) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+macro_rules! blocking_client {
+    ($($client:ty),*) => {$(
+        impl Client for $client {
+            async fn query(
+                &mut self,
+                statement: &str,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<Vec<Row>, Error> {
+                <$client>::query(self, statement, params)
+            }
+
+            async fn query_one(
+                &mut self,
+                statement: &str,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<Row, Error> {
+                <$client>::query_one(self, statement, params)
+            }
+
+            async fn query_opt(
+                &mut self,
+                statement: &str,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<Option<Row>, Error> {
+                <$client>::query_opt(self, statement, params)
+            }
+
+            async fn execute(
+                &mut self,
+                statement: &str,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<u64, Error> {
+                <$client>::execute(self, statement, params)
+            }
+
+            async fn batch_execute(&mut self, query: &str) -> Result<(), Error> {
+                <$client>::batch_execute(self, query)
+            }
+
+            async fn transaction(&mut self) -> Result<impl Transaction + '_, Error> {
+                <$client>::transaction(self)
+            }
+        }
+    )*};
+}
+
+macro_rules! async_client {
+    ($($client:ty),*) => {$(
+        impl Client for $client {
+            async fn query(
+                &mut self,
+                statement: &str,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<Vec<Row>, Error> {
+                <$client>::query(self, statement, params).await
+            }
+
+            async fn query_one(
+                &mut self,
+                statement: &str,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<Row, Error> {
+                <$client>::query_one(self, statement, params).await
+            }
+
+            async fn query_opt(
+                &mut self,
+                statement: &str,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<Option<Row>, Error> {
+                <$client>::query_opt(self, statement, params).await
+            }
+
+            async fn execute(
+                &mut self,
+                statement: &str,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<u64, Error> {
+                <$client>::execute(self, statement, params).await
+            }
+
+            async fn batch_execute(&mut self, query: &str) -> Result<(), Error> {
+                <$client>::batch_execute(self, query).await
+            }
+
+            async fn transaction(&mut self) -> Result<impl Transaction + '_, Error> {
+                <$client>::transaction(self).await
+            }
+        }
+    )*};
+}
+
+blocking_client!(postgres::Client, postgres::Transaction<'_>);
+async_client!(tokio_postgres::Client, tokio_postgres::Transaction<'_>);
+
+impl Transaction for postgres::Transaction<'_> {
+    async fn commit(self) -> Result<(), Error> {
+        postgres::Transaction::commit(self)
+    }
+}
+
+impl Transaction for tokio_postgres::Transaction<'_> {
+    async fn commit(self) -> Result<(), Error> {
+        tokio_postgres::Transaction::commit(self).await
+    }
+}
+
+/// Wakes the thread parked in `block_on`
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Run the job and sync logic on a blocking client, on the calling thread
+/// Blocking clients answer before their futures are returned, so it is usually ready on the
+/// first poll. A future that isn't parks the thread until it is woken.
+pub fn block_on<T>(future: impl Future<Output = T>) -> T {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! Schedule descriptions
+//! Describes schedules in plain English, e.g. `*/15 9-17 * * 1-5` is
+//! "every 15 minutes between 09:00 and 17:59, Monday through Friday",
+//! and reads simple English schedules such as "every day at 3am"
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use crate::errors::ValidationError;
+use crate::schedule::{is_full, parse_schedule, CronFields, ParsedSchedule};
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+fn values(bits: u64, low: u32, high: u32) -> Vec<u32> {
+    (low..=high)
+        .filter(|value| bits & 1 << value != 0)
+        .collect()
+}
+
+/// "a", "a and b", "a, b and c"
+fn join_and(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+/// Values of a field, with runs of 3 or more written as "a through b"
+fn describe_values(bits: u64, low: u32, high: u32, name: impl Fn(u32) -> String) -> String {
+    let values = values(bits, low, high);
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < values.len() {
+        let mut j = i;
+        while j + 1 < values.len() && values[j + 1] == values[j] + 1 {
+            j += 1;
+        }
+        if j - i >= 2 {
+            items.push(format!("{} through {}", name(values[i]), name(values[j])));
+        } else {
+            items.extend(values[i..=j].iter().map(|value| name(*value)));
+        }
+        i = j + 1;
+    }
+    join_and(&items)
+}
+
+/// First value and step if the values are first, first + step, ... up to the end of the field
+fn progression(bits: u64, low: u32, high: u32) -> Option<(u32, u32)> {
+    let values = values(bits, low, high);
+    let (first, second, last) = (*values.first()?, *values.get(1)?, *values.last()?);
+    let step = second - first;
+    let regular = values.windows(2).all(|pair| pair[1] - pair[0] == step);
+    // Evenly spaced across the wrap, two values like 8,20 read better as a list unless `*/n`
+    let wraps = (high - low + 1).is_multiple_of(step) && (values.len() > 2 || first == low);
+    (step > 1 && regular && wraps && last + step > high).then_some((first, step))
+}
+
+/// Hours that form one range, as "between 09:00 and 17:59"
+fn hour_window(hours: u64) -> Option<String> {
+    let values = values(hours, 0, 23);
+    let (first, last) = (*values.first()?, *values.last()?);
+    (last - first + 1 == values.len() as u32)
+        .then(|| format!("between {:02}:00 and {:02}:59", first, last))
+}
+
+fn plural(count: usize, word: &str) -> String {
+    if count == 1 {
+        word.to_string()
+    } else {
+        format!("{}s", word)
+    }
+}
+
+/// When during the day the fields fire, and whether that is at a few fixed times
+fn describe_time(fields: &CronFields) -> (String, bool) {
+    let all_hours = is_full(fields.hours, 0, 23);
+    let minutes = values(fields.minutes, 0, 59);
+    let hours = values(fields.hours, 0, 23);
+    let hour_list = || describe_values(fields.hours, 0, 23, |hour| hour.to_string());
+    let with_hours = |base: String| {
+        if all_hours {
+            base
+        } else if let Some(window) = hour_window(fields.hours) {
+            format!("{} {}", base, window)
+        } else {
+            format!("{} during hours {}", base, hour_list())
+        }
+    };
+
+    if is_full(fields.minutes, 0, 59) {
+        return (with_hours("every minute".to_string()), false);
+    }
+    if let Some((first, step)) = progression(fields.minutes, 0, 59) {
+        let base = match first {
+            0 => format!("every {} minutes", step),
+            _ => format!("every {} minutes starting at minute {}", step, first),
+        };
+        return (with_hours(base), false);
+    }
+
+    let minute_list = describe_values(fields.minutes, 0, 59, |minute| minute.to_string());
+    if all_hours {
+        return match minutes[..] {
+            [0] => ("every hour".to_string(), false),
+            _ => (
+                format!(
+                    "every hour at {} {}",
+                    plural(minutes.len(), "minute"),
+                    minute_list
+                ),
+                false,
+            ),
+        };
+    }
+    if let (Some((first, step)), [minute]) = (progression(fields.hours, 0, 23), &minutes[..]) {
+        let mut text = format!("every {} hours", step);
+        if first != 0 || *minute != 0 {
+            text = format!("{} starting at {:02}:{:02}", text, first, minute);
+        }
+        return (text, false);
+    }
+    if hours.len() * minutes.len() <= 6 {
+        let times: Vec<String> = hours
+            .iter()
+            .flat_map(|hour| {
+                minutes
+                    .iter()
+                    .map(move |minute| format!("{:02}:{:02}", hour, minute))
+            })
+            .collect();
+        return (format!("at {}", join_and(&times)), true);
+    }
+    let base = format!(
+        "every hour at {} {}",
+        plural(minutes.len(), "minute"),
+        minute_list
+    );
+    (with_hours(base), false)
+}
+
+/// Which days the fields fire on, None for every day
+fn describe_days(fields: &CronFields) -> Option<String> {
+    let any_day_of_month = is_full(fields.days, 1, 31);
+    let any_weekday = is_full(fields.weekdays, 0, 6);
+
+    let days = values(fields.days, 1, 31);
+    let mut day_items: Vec<String> = Vec::new();
+    if !days.is_empty() {
+        day_items.push(describe_values(fields.days, 1, 31, |day| day.to_string()));
+    }
+    if fields.last_day {
+        day_items.push("the last day".to_string());
+    }
+    let day_text = || match (&days[..], fields.last_day) {
+        ([], true) => "on the last day of the month".to_string(),
+        (_, true) => format!("on days {} of the month", join_and(&day_items)),
+        ([_], false) => format!("on day {} of the month", day_items[0]),
+        _ => format!("on days {} of the month", day_items[0]),
+    };
+
+    let weekdays = values(fields.weekdays, 0, 6);
+    let weekday_text = || {
+        let weekday_names = describe_values(fields.weekdays, 0, 6, |day| {
+            WEEKDAY_NAMES[day as usize].to_string()
+        });
+        if weekdays.len() >= 3 && !weekday_names.contains(',') && !weekday_names.contains(" and ") {
+            weekday_names
+        } else {
+            format!("on {}", weekday_names)
+        }
+    };
+
+    // A backwards range leaves one of them empty, then the other one alone decides,
+    // schedules that need both never fire and aren't described here
+    if days.is_empty() && !fields.last_day {
+        return (!any_weekday).then(weekday_text);
+    }
+    if weekdays.is_empty() {
+        return (!any_day_of_month || fields.last_day).then(day_text);
+    }
+
+    match (any_day_of_month && !fields.last_day, any_weekday) {
+        (true, true) => None,
+        (false, true) => Some(day_text()),
+        (true, false) => Some(weekday_text()),
+        // Like cron, both are needed if one of them starts with `*`, otherwise either will do
+        (false, false) if fields.days_star || fields.weekdays_star => {
+            Some(format!("{} if it is a {}", day_text(), join_or(&weekdays)))
+        }
+        (false, false) => Some(format!("{} or {}", day_text(), weekday_text())),
+    }
+}
+
+fn join_or(weekdays: &[u32]) -> String {
+    let names: Vec<&str> = weekdays
+        .iter()
+        .map(|day| WEEKDAY_NAMES[*day as usize])
+        .collect();
+    match &names[..] {
+        [rest @ .., last] if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => names.join(""),
+    }
+}
+
+/// Describe a schedule in plain English
+///
+/// # Example
+/// `*/15 9-17 * * 1-5` is "every 15 minutes between 09:00 and 17:59, Monday through Friday"
+pub fn describe_schedule(schedule: &str) -> Result<String, ValidationError> {
+    let fields = match parse_schedule(schedule)? {
+        ParsedSchedule::Interval(1) => return Ok("every second".to_string()),
+        ParsedSchedule::Interval(seconds) => return Ok(format!("every {} seconds", seconds)),
+        ParsedSchedule::Reboot => return Ok("when pg_cron starts".to_string()),
+        ParsedSchedule::Cron(fields) if fields.never_fires() => return Ok("never".to_string()),
+        ParsedSchedule::Cron(fields) => fields,
+    };
+
+    let (mut text, fixed_times) = describe_time(&fields);
+    match describe_days(&fields) {
+        Some(days) => text = format!("{}, {}", text, days),
+        None if fixed_times => text = format!("{} every day", text),
+        None => {}
+    }
+    if !is_full(fields.months, 1, 12) {
+        let months = describe_values(fields.months, 1, 12, |month| {
+            MONTH_NAMES[month as usize - 1].to_string()
+        });
+        text = format!("{}, in {}", text, months);
+    }
+    Ok(text)
+}
+
+static TIME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d{1,2})(?::(\d{2}))?\s*(am|pm)?$").unwrap());
+static LEADING_TIME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^at (\S+(?: [ap]m)?) (every .+|daily|weekly|monthly)$").unwrap());
+static EVERY_N: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^every (\d+) (second|minute|hour)s?$").unwrap());
+static HOURLY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:every hour|hourly)(?: at minute (\d{1,2}))?$").unwrap());
+static MONTHLY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:every month|monthly)(?: on the (?:(\d{1,2})(?:st|nd|rd|th)?|(last)) day| on the (\d{1,2})(?:st|nd|rd|th))?$")
+        .unwrap()
+});
+static WEEKDAYS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^every ([a-z, ]+)$").unwrap());
+
+/// Hour and minute of "3am", "3:30 pm", "15:00", "noon" or "midnight"
+pub(crate) fn parse_time(time: &str) -> Result<(u32, u32), ValidationError> {
+    let invalid = || ValidationError::new(format!("Invalid time {}", time));
+    match time {
+        "noon" => return Ok((12, 0)),
+        "midnight" => return Ok((0, 0)),
+        _ => {}
+    }
+    let caps = TIME.captures(time).ok_or_else(invalid)?;
+    let hour: u32 = caps[1].parse().map_err(|_| invalid())?;
+    let minute: u32 = caps
+        .get(2)
+        .map_or(Ok(0), |m| m.as_str().parse())
+        .map_err(|_| invalid())?;
+    let hour = match caps.get(3).map(|m| m.as_str()) {
+        Some(_) if !(1..=12).contains(&hour) => return Err(invalid()),
+        Some("am") => hour % 12,
+        Some(_) => hour % 12 + 12,
+        None => hour,
+    };
+    if hour > 23 || minute > 59 {
+        return Err(invalid());
+    }
+    Ok((hour, minute))
+}
+
+/// Day of the week of "mon", "monday" or "mondays"
+pub(crate) fn parse_weekday(day: &str) -> Option<u32> {
+    let day = day
+        .strip_suffix('s')
+        .filter(|day| day.len() > 3)
+        .unwrap_or(day);
+    WEEKDAY_NAMES
+        .iter()
+        .position(|name| {
+            let name = name.to_lowercase();
+            day == name || (day.len() == 3 && name.starts_with(day))
+        })
+        .map(|day| day as u32)
+}
+
+/// Read a schedule written in English
+///
+/// Understands intervals ("every 15 minutes", "every 2 hours", "every 30 seconds"), days
+/// ("every day at 3am", "every weekday at 09:30", "every monday and friday at noon"),
+/// months ("every month on the 1st at 6pm", "every month on the last day") and
+/// "every year", "every hour at minute 15" and "at startup"
+///
+/// # Returns
+/// The schedule in pg_cron syntax, e.g. `0 3 * * *` for "every day at 3am"
+pub fn schedule_from_text(text: &str) -> Result<String, ValidationError> {
+    let mut text = text
+        .trim()
+        .trim_end_matches('.')
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    // "at 3am every day" reads like "every day at 3am"
+    if let Some(caps) = LEADING_TIME.captures(&text) {
+        text = format!("{} at {}", &caps[2], &caps[1]);
+    }
+
+    let (rest, time) = match text.rsplit_once(" at ") {
+        Some((rest, time)) if !time.starts_with("minute") => {
+            (rest.to_string(), Some(parse_time(time)?))
+        }
+        _ => (text.clone(), None),
+    };
+    let (hour, minute) = time.unwrap_or((0, 0));
+    let at = |days: &str| format!("{} {} {}", minute, hour, days);
+
+    let schedule = if let Some(caps) = EVERY_N.captures(&rest).filter(|_| time.is_none()) {
+        let n: u32 = caps[1].parse().unwrap_or(0);
+        match &caps[2] {
+            "second" => format!("{} seconds", n),
+            "minute" if (1..=59).contains(&n) => format!("*/{} * * * *", n),
+            "hour" if (1..=23).contains(&n) => format!("0 */{} * * *", n),
+            unit => return Err(format!("Can't run every {} {}s", n, unit).into()),
+        }
+    } else if let Some(caps) = HOURLY.captures(&rest).filter(|_| time.is_none()) {
+        format!("{} * * * *", caps.get(1).map_or("0", |m| m.as_str()))
+    } else if let Some(caps) = MONTHLY.captures(&rest) {
+        let day = match (caps.get(1).or(caps.get(3)), caps.get(2)) {
+            (_, Some(_)) => "$",
+            (Some(day), _) => day.as_str(),
+            (None, None) => "1",
+        };
+        at(&format!("{} * *", day))
+    } else {
+        match rest.as_str() {
+            "every second" => "1 second".to_string(),
+            "every minute" if time.is_none() => "* * * * *".to_string(),
+            "every day" | "daily" | "each day" => at("* * *"),
+            "every weekday" | "every week day" | "weekdays" => at("* * 1-5"),
+            "every weekend" | "every weekend day" | "weekends" => at("* * 0,6"),
+            "every week" | "weekly" => at("* * 0"),
+            "every year" | "yearly" | "annually" => at("1 1 *"),
+            "at startup" | "on startup" | "at reboot" | "on reboot" if time.is_none() => {
+                "@reboot".to_string()
+            }
+            _ => {
+                let days = WEEKDAYS
+                    .captures(&rest)
+                    .and_then(|caps| {
+                        caps[1]
+                            .split([',', ' '])
+                            .filter(|word| !word.is_empty() && *word != "and")
+                            .map(parse_weekday)
+                            .collect::<Option<Vec<u32>>>()
+                    })
+                    .ok_or_else(|| {
+                        ValidationError::new(format!(
+                            "Could not read a schedule from '{}', try e.g. 'every day at 3am', 'every 15 minutes' or 'every monday at 09:30'",
+                            text
+                        ))
+                    })?;
+                let days: Vec<String> = days.iter().map(|day| day.to_string()).collect();
+                at(&format!("* * {}", days.join(",")))
+            }
+        }
+    };
+
+    parse_schedule(&schedule)?;
+    Ok(schedule)
+}
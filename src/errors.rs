@@ -1,7 +1,210 @@
-use pyo3::exceptions::{PyOSError, PyValueError};
+//! Errors, and the Python exceptions they are raised as
+//!
+//! ```text
+//! PgCronnerError(Exception)
+//! ├── DatabaseError(OSError)
+//! │   ├── ConnectionError(builtins.ConnectionError)
+//! │   ├── JobNotFound
+//! │   ├── JobAlreadyExists
+//! │   └── PgCronNotInstalled
+//! └── ValidationError(ValueError)
+//!     └── InvalidSchedule
+//! ```
+//!
+//! Every exception has a `sqlstate` attribute, the SQLSTATE code of the server's error if there
+//! was one, and a `job_name` attribute, the job the error is about if there is one.
+//! Subclassing OSError and ValueError keeps code catching those working.
+
+use postgres::error::SqlState;
 use pyo3::prelude::*;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyDict, PyTuple, PyType};
 use std::fmt;
 
+/// Which Python exception an error is raised as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Database,
+    Connection,
+    JobNotFound,
+    JobAlreadyExists,
+    PgCronNotInstalled,
+    Validation,
+    InvalidSchedule,
+}
+
+/// The exception classes, created once and shared by every import of the module
+struct Exceptions {
+    base: Py<PyType>,
+    database: Py<PyType>,
+    connection: Py<PyType>,
+    job_not_found: Py<PyType>,
+    job_already_exists: Py<PyType>,
+    pg_cron_not_installed: Py<PyType>,
+    validation: Py<PyType>,
+    invalid_schedule: Py<PyType>,
+}
+
+static EXCEPTIONS: GILOnceCell<Exceptions> = GILOnceCell::new();
+
+/// Create an exception class in the pgcronner module
+fn new_exception(py: Python, name: &str, doc: &str, bases: &PyTuple) -> PyResult<Py<PyType>> {
+    let dict = PyDict::new(py);
+    dict.set_item("__module__", "pgcronner")?;
+    dict.set_item("__doc__", doc)?;
+    dict.set_item("sqlstate", py.None())?;
+    dict.set_item("job_name", py.None())?;
+    let class = py.get_type::<PyType>().call1((name, bases, dict))?;
+    Ok(class.downcast::<PyType>()?.into())
+}
+
+impl Exceptions {
+    fn get(py: Python) -> PyResult<&'static Self> {
+        EXCEPTIONS.get_or_try_init(py, || {
+            let builtins = py.import("builtins")?;
+            let bases = |classes: &[&PyAny]| PyTuple::new(py, classes);
+
+            let base = new_exception(
+                py,
+                "PgCronnerError",
+                "Base class of the errors raised by pgcronner",
+                bases(&[builtins.getattr("Exception")?]),
+            )?;
+            let database = new_exception(
+                py,
+                "DatabaseError",
+                "A query failed",
+                bases(&[base.as_ref(py), builtins.getattr("OSError")?]),
+            )?;
+            let validation = new_exception(
+                py,
+                "ValidationError",
+                "A job or an argument is invalid",
+                bases(&[base.as_ref(py), builtins.getattr("ValueError")?]),
+            )?;
+            Ok(Self {
+                connection: new_exception(
+                    py,
+                    "ConnectionError",
+                    "Could not connect to the database, or the connection was lost",
+                    bases(&[database.as_ref(py), builtins.getattr("ConnectionError")?]),
+                )?,
+                job_not_found: new_exception(
+                    py,
+                    "JobNotFound",
+                    "No job has this name",
+                    bases(&[database.as_ref(py)]),
+                )?,
+                job_already_exists: new_exception(
+                    py,
+                    "JobAlreadyExists",
+                    "A job with this name was already added",
+                    bases(&[database.as_ref(py)]),
+                )?,
+                pg_cron_not_installed: new_exception(
+                    py,
+                    "PgCronNotInstalled",
+                    "The pg_cron extension is missing, or too old for a job",
+                    bases(&[database.as_ref(py)]),
+                )?,
+                invalid_schedule: new_exception(
+                    py,
+                    "InvalidSchedule",
+                    "A schedule pg_cron can't run",
+                    bases(&[validation.as_ref(py)]),
+                )?,
+                base,
+                database,
+                validation,
+            })
+        })
+    }
+
+    fn class(&self, kind: ErrorKind) -> &Py<PyType> {
+        match kind {
+            ErrorKind::Database => &self.database,
+            ErrorKind::Connection => &self.connection,
+            ErrorKind::JobNotFound => &self.job_not_found,
+            ErrorKind::JobAlreadyExists => &self.job_already_exists,
+            ErrorKind::PgCronNotInstalled => &self.pg_cron_not_installed,
+            ErrorKind::Validation => &self.validation,
+            ErrorKind::InvalidSchedule => &self.invalid_schedule,
+        }
+    }
+}
+
+/// Add the exception classes to the module
+pub fn add_exceptions(py: Python, m: &PyModule) -> PyResult<()> {
+    let exceptions = Exceptions::get(py)?;
+    for class in [
+        &exceptions.base,
+        &exceptions.database,
+        &exceptions.connection,
+        &exceptions.job_not_found,
+        &exceptions.job_already_exists,
+        &exceptions.pg_cron_not_installed,
+        &exceptions.validation,
+        &exceptions.invalid_schedule,
+    ] {
+        let class = class.as_ref(py);
+        m.add(class.name()?, class)?;
+    }
+    Ok(())
+}
+
+/// The exception for an error, with its `sqlstate` and `job_name` attributes set
+fn new_err(
+    kind: ErrorKind,
+    message: String,
+    sqlstate: Option<String>,
+    job_name: Option<String>,
+) -> PyErr {
+    Python::with_gil(|py| {
+        let exception = Exceptions::get(py)
+            .and_then(|exceptions| exceptions.class(kind).as_ref(py).call1((message,)))
+            .and_then(|exception| {
+                exception.setattr("sqlstate", sqlstate)?;
+                exception.setattr("job_name", job_name)?;
+                Ok(exception)
+            });
+        match exception {
+            Ok(exception) => PyErr::from_value(exception),
+            Err(e) => e,
+        }
+    })
+}
+
+/// Tell what kind of failure a postgres error is, from its SQLSTATE
+fn classify(error: &postgres::Error) -> ErrorKind {
+    let Some(code) = error.code() else {
+        // Errors without a SQLSTATE didn't come from the server
+        let io =
+            std::error::Error::source(error).is_some_and(|source| source.is::<std::io::Error>());
+        return match error.is_closed() || io {
+            true => ErrorKind::Connection,
+            false => ErrorKind::Database,
+        };
+    };
+    // Connection exceptions, and the server shutting down
+    if code.code().starts_with("08") || code.code().starts_with("57P") {
+        return ErrorKind::Connection;
+    }
+    // The cron schema, cron.job or cron.schedule() are missing
+    let missing = [
+        SqlState::INVALID_SCHEMA_NAME,
+        SqlState::UNDEFINED_TABLE,
+        SqlState::UNDEFINED_FUNCTION,
+    ];
+    let message = error.as_db_error().map_or("", |e| e.message());
+    let in_cron = ["\"cron\"", "\"cron.", " cron."]
+        .iter()
+        .any(|name| message.contains(name));
+    if missing.contains(code) && in_cron {
+        return ErrorKind::PgCronNotInstalled;
+    }
+    ErrorKind::Database
+}
+
 /// ValidationError
 ///
 /// A ValidationError is raised when a validation error occurs
@@ -12,11 +215,41 @@ use std::fmt;
 #[derive(Debug, Clone)]
 pub struct ValidationError {
     message: String,
+    kind: ErrorKind,
+    job_name: Option<String>,
 }
 
 impl ValidationError {
     pub fn new(message: String) -> Self {
-        Self { message }
+        Self {
+            message,
+            kind: ErrorKind::Validation,
+            job_name: None,
+        }
+    }
+
+    /// An error in a schedule, raised as InvalidSchedule
+    pub fn invalid_schedule(message: String) -> Self {
+        Self {
+            kind: ErrorKind::InvalidSchedule,
+            ..Self::new(message)
+        }
+    }
+
+    /// The error is about this job, kept if it was already set
+    pub fn with_job(self, name: &str) -> Self {
+        Self {
+            job_name: self.job_name.or_else(|| Some(name.to_string())),
+            ..self
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn job_name(&self) -> Option<&str> {
+        self.job_name.as_deref()
     }
 }
 
@@ -30,13 +263,13 @@ impl fmt::Display for ValidationError {
 
 impl std::convert::From<ValidationError> for PyErr {
     fn from(error: ValidationError) -> PyErr {
-        PyValueError::new_err(error.message)
+        new_err(error.kind, error.message, None, error.job_name)
     }
 }
 
 impl From<String> for ValidationError {
     fn from(error: String) -> ValidationError {
-        ValidationError { message: error }
+        ValidationError::new(error)
     }
 }
 
@@ -56,13 +289,73 @@ impl From<ValidationError> for String {
 #[derive(Debug, Clone)]
 pub struct DbError {
     message: String,
+    kind: ErrorKind,
+    sqlstate: Option<String>,
+    job_name: Option<String>,
 }
 
 impl std::error::Error for DbError {}
 
 impl DbError {
     pub fn new(message: String) -> Self {
-        Self { message }
+        Self {
+            message,
+            kind: ErrorKind::Database,
+            sqlstate: None,
+            job_name: None,
+        }
+    }
+
+    /// A failed query or connection, with the server's message and SQLSTATE
+    ///
+    /// # Arguments
+    /// * `context` - What failed, e.g. "Could not add job to DB"
+    /// * `error` - The error of the postgres client
+    pub fn postgres(context: &str, error: &postgres::Error) -> Self {
+        let message = match std::error::Error::source(error) {
+            Some(source) => format!("{}: {}: {}", context, error, source),
+            None => format!("{}: {}", context, error),
+        };
+        Self {
+            kind: classify(error),
+            sqlstate: error.code().map(|code| code.code().to_string()),
+            ..Self::new(message)
+        }
+    }
+
+    /// No job has this name, raised as JobNotFound
+    pub fn job_not_found(name: &str) -> Self {
+        Self::new(format!("Job {} not found!", name))
+            .with_kind(ErrorKind::JobNotFound)
+            .with_job(name)
+    }
+
+    pub fn with_kind(self, kind: ErrorKind) -> Self {
+        Self { kind, ..self }
+    }
+
+    /// The error is about this job, kept if it was already set
+    pub fn with_job(self, name: &str) -> Self {
+        Self {
+            job_name: self.job_name.or_else(|| Some(name.to_string())),
+            ..self
+        }
+    }
+
+    /// Prefix the message, keeping the kind, SQLSTATE and job
+    pub fn context(self, context: &str) -> Self {
+        Self {
+            message: format!("{}: {}", context, self.message),
+            ..self
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn sqlstate(&self) -> Option<&str> {
+        self.sqlstate.as_deref()
     }
 }
 
@@ -74,7 +367,7 @@ impl fmt::Display for DbError {
 
 impl std::convert::From<DbError> for PyErr {
     fn from(error: DbError) -> PyErr {
-        PyOSError::new_err(error.message)
+        new_err(error.kind, error.message, error.sqlstate, error.job_name)
     }
 }
 
@@ -86,7 +379,7 @@ impl AsRef<str> for DbError {
 
 impl From<String> for DbError {
     fn from(error: String) -> DbError {
-        DbError { message: error }
+        DbError::new(error)
     }
 }
 
@@ -96,9 +389,9 @@ impl From<DbError> for String {
     }
 }
 
-/// DbError
+/// ConvertError
 ///
-/// A DbError is raised when a database error occurs
+/// A ConvertError is raised when a row can't be converted to a job, as ValidationError
 ///
 /// # Arguments
 /// * `message` - Error message
@@ -124,7 +417,7 @@ impl fmt::Display for ConvertError {
 
 impl std::convert::From<ConvertError> for PyErr {
     fn from(error: ConvertError) -> PyErr {
-        PyValueError::new_err(error.message)
+        new_err(ErrorKind::Validation, error.message, None, None)
     }
 }
 
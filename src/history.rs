@@ -0,0 +1,328 @@
+//! Run history
+//! Runs of pgcronner jobs, read from `cron.job_run_details` or from the runs table
+//! pgcronner copies them to, `<table_name>_runs`
+
+use chrono::{DateTime, Utc};
+use pyo3::prelude::*;
+use pyo3::types::PyDateTime;
+use std::fmt;
+
+use crate::client::Client;
+use crate::errors::{DbError, ValidationError};
+use crate::namespace::Namespace;
+use crate::utils::{qualified_table, quote_ident, to_py_datetime};
+
+/// Statuses pg_cron writes to `cron.job_run_details`
+pub const RUN_STATUSES: [&str; 6] = [
+    "starting",
+    "running",
+    "sending",
+    "connecting",
+    "succeeded",
+    "failed",
+];
+
+/// A single run of a job
+///
+/// # Arguments
+/// * `runid` - Id of the run in `cron.job_run_details`
+/// * `jobid` - Id of the job in `cron.job` at the time of the run
+/// * `job` - Name of the job
+/// * `pid` - Backend pid that ran the command
+/// * `status` - One of starting, running, sending, connecting, succeeded, failed
+/// * `return_message` - Command tag or error message
+/// * `start_time` - When the run started
+/// * `end_time` - When the run ended, None while it is running
+///
+#[derive(Debug, Clone)]
+#[pyclass(module = "pgcronner")]
+pub struct JobRun {
+    #[pyo3(get)]
+    pub runid: i64,
+    #[pyo3(get)]
+    pub jobid: i64,
+    #[pyo3(get)]
+    pub job: String,
+    #[pyo3(get)]
+    pub pid: Option<i32>,
+    #[pyo3(get)]
+    pub status: String,
+    #[pyo3(get)]
+    pub return_message: Option<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+impl JobRun {
+    /// Duration of the run, None until it ended
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        Some(self.end_time? - self.start_time?)
+    }
+}
+
+#[pymethods]
+impl JobRun {
+    #[getter(start_time)]
+    fn py_start_time<'p>(&self, py: Python<'p>) -> PyResult<Option<&'p PyDateTime>> {
+        to_py_datetime(py, self.start_time)
+    }
+
+    #[getter(end_time)]
+    fn py_end_time<'p>(&self, py: Python<'p>) -> PyResult<Option<&'p PyDateTime>> {
+        to_py_datetime(py, self.end_time)
+    }
+
+    /// Duration of the run in seconds, None until it ended
+    #[getter(duration)]
+    fn py_duration(&self) -> Option<f64> {
+        self.duration()
+            .map(|duration| duration.num_microseconds().unwrap_or(i64::MAX) as f64 / 1_000_000.0)
+    }
+
+    pub fn __str__(&self) -> PyResult<String> {
+        Ok(self.to_string())
+    }
+
+    pub fn __repr__(&self) -> PyResult<String> {
+        let time =
+            |time: Option<DateTime<Utc>>| time.map_or("None".to_string(), |time| time.to_rfc3339());
+        Ok(format!(
+            "JobRun(runid={}, job={}, status={}, start_time={}, end_time={})",
+            self.runid,
+            self.job,
+            self.status,
+            time(self.start_time),
+            time(self.end_time)
+        ))
+    }
+}
+
+impl fmt::Display for JobRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} #{}: {}", self.job, self.runid, self.status)?;
+        if let Some(start_time) = self.start_time {
+            write!(f, " at {}", start_time)?;
+        }
+        if let Some(message) = &self.return_message {
+            write!(f, " ({})", message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Filters for `job_runs`
+///
+/// # Arguments
+/// * `limit` - Maximum number of runs
+/// * `offset` - Number of runs to skip, for pagination
+/// * `since` - Only runs started at or after this time
+/// * `status` - Only runs with this status
+///
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    pub limit: i64,
+    pub offset: i64,
+    pub since: Option<DateTime<Utc>>,
+    pub status: Option<String>,
+}
+
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        Self {
+            limit: 100,
+            offset: 0,
+            since: None,
+            status: None,
+        }
+    }
+}
+
+impl HistoryQuery {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.limit < 0 || self.offset < 0 {
+            return Err("limit and offset can't be negative".to_string().into());
+        }
+        match &self.status {
+            Some(status) if !RUN_STATUSES.contains(&status.as_str()) => Err(format!(
+                "Invalid status {}, use one of {}",
+                status,
+                RUN_STATUSES.join(", ")
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Runs of `cron.job_run_details` with the current name of their job in `cron.job`
+/// Runs of jobs that were unscheduled since have no job to join and are left out
+const RUN_DETAILS: &str = "
+    SELECT d.runid, d.jobid, j.jobname AS name, d.job_pid AS pid, d.status, d.return_message,
+        d.start_time, d.end_time
+    FROM cron.job_run_details d
+    JOIN cron.job j ON j.jobid = d.jobid AND j.username = current_user";
+
+async fn query_runs(
+    client: &mut impl Client,
+    source: &str,
+    jobname: &str,
+    query: &HistoryQuery,
+) -> Result<Vec<JobRun>, DbError> {
+    query.validate().map_err(|e| DbError::new(e.into()))?;
+
+    let rows = client
+        .query(
+            &format!(
+                "
+                SELECT runid, jobid, name, pid, status, return_message, start_time, end_time
+                FROM ({source}) runs
+                WHERE name = $1
+                    AND ($2::timestamptz IS NULL OR start_time >= $2)
+                    AND ($3::text IS NULL OR status = $3)
+                ORDER BY start_time DESC NULLS FIRST, runid DESC
+                LIMIT $4 OFFSET $5"
+            ),
+            &[
+                &jobname,
+                &query.since,
+                &query.status,
+                &query.limit,
+                &query.offset,
+            ],
+        )
+        .await
+        .map_err(|e| DbError::postgres("Could not fetch run history", &e))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| JobRun {
+            runid: row.get(0),
+            jobid: row.get(1),
+            job: row.get(2),
+            pid: row.get(3),
+            status: row.get(4),
+            return_message: row.get(5),
+            start_time: row.get(6),
+            end_time: row.get(7),
+        })
+        .collect())
+}
+
+/// Runs of a job in `cron.job_run_details`, most recent first
+pub async fn job_runs(
+    client: &mut impl Client,
+    jobname: &str,
+    query: &HistoryQuery,
+) -> Result<Vec<JobRun>, DbError> {
+    query_runs(client, RUN_DETAILS, jobname, query).await
+}
+
+/// Runs of a job copied to the runs table, most recent first
+///
+/// # Arguments
+/// * `runs_table` - Quoted name of the runs table
+pub async fn archived_runs(
+    client: &mut impl Client,
+    runs_table: &str,
+    jobname: &str,
+    query: &HistoryQuery,
+) -> Result<Vec<JobRun>, DbError> {
+    query_runs(
+        client,
+        &format!("SELECT * FROM {}", runs_table),
+        jobname,
+        query,
+    )
+    .await
+}
+
+/// Quoted name of the table the runs of `table_name`'s jobs are copied to
+pub fn runs_table(schema: Option<&str>, table_name: &str) -> String {
+    qualified_table(schema, &format!("{}_runs", table_name))
+}
+
+/// Create the table runs are copied to, keyed by job name so history survives
+/// the job being recreated with another jobid and pg_cron purging its own history
+///
+/// # Returns
+/// The quoted name of the runs table
+pub async fn create_runs_table(
+    client: &mut impl Client,
+    schema: Option<&str>,
+    table_name: &str,
+) -> Result<String, DbError> {
+    let runs_table = runs_table(schema, table_name);
+    let index = quote_ident(&format!("{}_runs_name_start_time_idx", table_name));
+    client
+        .batch_execute(&format!(
+            "
+            CREATE TABLE IF NOT EXISTS {runs_table} (
+            runid BIGINT PRIMARY KEY,
+            jobid BIGINT NOT NULL,
+            name VARCHAR(255) NOT NULL,
+            pid INTEGER,
+            status TEXT,
+            return_message TEXT,
+            start_time TIMESTAMPTZ,
+            end_time TIMESTAMPTZ);
+            CREATE INDEX IF NOT EXISTS {index} ON {runs_table} (name, start_time);"
+        ))
+        .await
+        .map_err(|e| DbError::postgres("Could not create runs table", &e))?;
+    Ok(runs_table)
+}
+
+/// Copy the runs of the namespace's jobs from `cron.job_run_details` to the runs table
+/// Runs that were still going on last time are updated
+///
+/// # Returns
+/// The number of runs copied or updated
+pub async fn archive_runs(
+    client: &mut impl Client,
+    runs_table: &str,
+    namespace: &Namespace,
+) -> Result<u64, DbError> {
+    client
+        .execute(
+            &format!(
+                "
+                INSERT INTO {runs_table} AS runs
+                SELECT * FROM ({RUN_DETAILS}) details
+                WHERE starts_with(details.name, $1)
+                    AND NOT EXISTS (
+                        SELECT 1 FROM {runs_table} done
+                        WHERE done.runid = details.runid AND done.end_time IS NOT NULL
+                    )
+                ON CONFLICT (runid) DO UPDATE SET
+                    status = EXCLUDED.status,
+                    return_message = EXCLUDED.return_message,
+                    end_time = EXCLUDED.end_time
+                WHERE (runs.status, runs.return_message, runs.end_time)
+                    IS DISTINCT FROM (EXCLUDED.status, EXCLUDED.return_message, EXCLUDED.end_time)"
+            ),
+            &[&namespace.prefix()],
+        )
+        .await
+        .map_err(|e| DbError::postgres("Could not archive runs", &e))
+}
+
+/// Delete the namespace's runs that started more than `days` days ago from the runs table
+///
+/// # Returns
+/// The number of runs deleted
+pub async fn prune_runs(
+    client: &mut impl Client,
+    runs_table: &str,
+    namespace: &Namespace,
+    days: i32,
+) -> Result<u64, DbError> {
+    client
+        .execute(
+            &format!(
+                "DELETE FROM {} WHERE starts_with(name, $1) AND start_time < now() - make_interval(days => $2)",
+                runs_table
+            ),
+            &[&namespace.prefix(), &days],
+        ).await
+        .map_err(|e| DbError::postgres("Could not prune runs", &e))
+}
@@ -1,24 +1,28 @@
 //! Job struct
 //! A Job is a scheduled SQL command
 
-use crate::get_stored_procedure_name;
 use chrono::{DateTime, Utc};
-use cron_parser::parse;
+use chrono_tz::Tz;
 use log::debug;
 use pyo3::prelude::*;
-use pyo3::types::PyDict;
+use pyo3::types::{PyDateTime, PyDelta, PyDict};
 use std::fmt;
 
+use crate::describe::describe_schedule;
 use crate::errors::ValidationError;
-use crate::PREFIX;
+use crate::namespace::{Namespace, BASE_PREFIX};
+use crate::schedule::{expand_hashed, next_runs, parse_schedule, ParsedSchedule, ScheduleArg};
+use crate::timezone::parse_timezone;
+use crate::utils::{from_py_datetime, to_py_datetime_in};
 
 /// A Job is a scheduled SQL command
 ///
 /// # Arguments
 /// * `name` - Name of the job
-/// * `schedule` - cron schedule
+/// * `schedule` - cron schedule, macro or interval of seconds, `H` picks a value from the name
 /// * `command` - E.g. CALL my_command();
 /// * `source` - SQL source
+/// * `timezone` - Timezone the schedule is written in, the server's cron.timezone if None
 ///
 #[derive(Debug, Clone)]
 #[pyclass]
@@ -32,44 +36,16 @@ pub struct Job {
     #[pyo3(get, set)]
     pub source: String, // SQL source
     pub last_run: Option<DateTime<Utc>>,
-    pub active: bool,
-}
-
-fn parse_command(command: &str, name: &str) -> String {
-    match command.contains("CALL") {
-        true => {
-            let name = get_stored_procedure_name(command, name);
-
-            if name.starts_with(PREFIX) {
-                format!("CALL {}();", name)
-            } else {
-                format!("CALL {}{}();", PREFIX, name)
-            }
-        }
-        false => command.clone().to_string(),
-    }
-}
-
-fn format_name(name: &str) -> Result<String, ValidationError> {
-    match name.starts_with(PREFIX) {
-        true => Ok(name.to_string()),
-        false => Ok(format!(
-            "{}{}",
-            PREFIX,
-            name.trim()
-                .replace(' ', "")
-                .to_lowercase()
-                .replace('-', "_")
-        )),
-    }
+    #[pyo3(get, set)]
+    pub active: bool, // Inactive jobs are disabled or unscheduled on sync
+    #[pyo3(get, set)]
+    pub timezone: Option<String>, // E.g. Europe/Berlin, None for cron.timezone
 }
 
+/// Check a schedule against pg_cron's grammar, see `schedule::parse_schedule`
 pub fn schedule_is_valid(schedule: &str) -> Result<(), ValidationError> {
-    let now: DateTime<Utc> = Utc::now();
-    match parse(schedule, &now) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(format!("Invalid schedule: {}", e).into()),
-    }
+    parse_schedule(schedule)?;
+    Ok(())
 }
 
 #[pymethods]
@@ -78,19 +54,37 @@ impl Job {
     ///
     /// # Arguments
     /// * `name` - Name of the job
-    /// * `schedule` - cron schedule
+    /// * `schedule` - Schedule, or cron schedule, macro such as @daily, or interval such as
+    ///   "30 seconds"
     /// * `command` - E.g. CALL my_command()
     /// * `source` - SQL source
+    /// * `timezone` - Timezone the schedule is written in (optional), e.g. Europe/Berlin.
+    ///   By default the schedule is read in the server's cron.timezone
     ///
     /// # Example
     /// ```
     /// job = Job("my_job", "0 0 * * *", "CALL my_command();", "SELECT * FROM my_table;")
+    /// job = Job("my_poller", "10 seconds", "SELECT poll();", "")
+    /// job = Job("my_report", "*/30 * * * *", "SELECT report();", "", timezone="Europe/Berlin")
+    /// job = Job("my_backup", Schedule.from_text("every day at 3am"), "SELECT backup();", "")
     /// ```
     ///
     #[new]
-    pub fn new(name: String, schedule: String, command: String, source: String) -> Self {
-        let name = format_name(&name).unwrap();
-        let command = parse_command(&command, &name);
+    #[pyo3(
+        signature = (name, schedule, command, source, timezone=None),
+        text_signature = "(name, schedule, command, source, timezone=None)"
+    )]
+    pub fn new(
+        name: String,
+        schedule: ScheduleArg,
+        command: String,
+        source: String,
+        timezone: Option<String>,
+    ) -> Self {
+        let namespace = Namespace::default();
+        let name = namespace.qualify_name(&name);
+        let command = namespace.qualify_command(&command, &name);
+        let schedule = schedule.into_expression();
 
         Self {
             name,
@@ -99,6 +93,7 @@ impl Job {
             source,
             last_run: None,
             active: true,
+            timezone,
         }
     }
 
@@ -112,19 +107,111 @@ impl Job {
 
         dict.set_item("name", self.name.clone())?;
         dict.set_item("schedule", self.schedule.clone())?;
+        dict.set_item("expanded_schedule", self.expanded_schedule().ok())?;
+        dict.set_item("schedule_kind", self.schedule_kind().ok())?;
         dict.set_item("command", self.command.clone())?;
         dict.set_item("source", self.source.clone())?;
         dict.set_item("last_run", last_run)?;
         dict.set_item("active", self.active)?;
+        dict.set_item("timezone", self.timezone.clone())?;
 
         Ok(dict.into())
     }
 
+    /// The schedule pg_cron gets, with `H` tokens replaced by values picked from the job name
+    ///
+    /// # Example
+    /// ```
+    /// job = Job("my_job", "H * * * *", "SELECT 1", "")
+    /// job.expanded_schedule  # e.g. "17 * * * *", the same every time for this name
+    /// ```
+    #[getter]
+    pub fn expanded_schedule(&self) -> Result<String, ValidationError> {
+        Ok(expand_hashed(&self.schedule, &self.name)?)
+    }
+
+    /// Kind of the job's schedule: cron, interval (every n seconds) or reboot
+    #[getter]
+    pub fn schedule_kind(&self) -> Result<&'static str, ValidationError> {
+        Ok(parse_schedule(&self.expanded_schedule()?)?.kind())
+    }
+
+    /// Describe the schedule in plain English, followed by the timezone if the job has one
+    ///
+    /// # Example
+    /// ```
+    /// job = Job("my_job", "*/15 9-17 * * 1-5", "SELECT 1", "")
+    /// job.describe_schedule()  # every 15 minutes between 09:00 and 17:59, Monday through Friday
+    /// ```
+    pub fn describe_schedule(&self) -> Result<String, ValidationError> {
+        let description = describe_schedule(&self.expanded_schedule()?)?;
+        Ok(match &self.timezone {
+            Some(timezone) => format!("{} ({})", description, timezone),
+            None => description,
+        })
+    }
+
+    /// Time between runs of an interval schedule, None for other schedules
+    #[getter]
+    pub fn interval<'p>(&self, py: Python<'p>) -> PyResult<Option<&'p PyDelta>> {
+        match parse_schedule(&self.expanded_schedule()?).map_err(ValidationError::from)? {
+            ParsedSchedule::Interval(seconds) => {
+                Ok(Some(PyDelta::new(py, 0, seconds as i32, 0, false)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Next times the job's schedule fires
+    /// Interval schedules are counted from the job's last run, if it ran
+    /// Without a connection the server's `cron.timezone` isn't known, a job without a timezone
+    /// is computed in UTC, see `PgCronner.next_runs`
+    ///
+    /// # Arguments
+    /// * `n` - Number of runs
+    /// * `after` - Only runs after this datetime, defaults to now
+    ///
+    /// # Example
+    /// ```
+    /// job = Job("my_job", "0 3 * * *", "SELECT 1", "")
+    /// job.next_runs(3)
+    /// ```
+    ///
+    /// # Returns
+    /// A list of aware datetimes in the job's timezone, UTC if it has none
+    #[pyo3(signature = (n, after=None), text_signature = "($self, n, after=None)")]
+    pub fn next_runs<'p>(
+        &self,
+        py: Python<'p>,
+        n: usize,
+        after: Option<&PyDateTime>,
+    ) -> PyResult<Vec<&'p PyDateTime>> {
+        let after = match after {
+            Some(after) => from_py_datetime(after)?,
+            None => Utc::now(),
+        };
+        self.runs_after(after, n, Tz::UTC)?
+            .into_iter()
+            .map(|time| to_py_datetime_in(py, time, self.timezone.as_deref()))
+            .collect()
+    }
+
+    /// Next time the job's schedule fires in the job's timezone, UTC if it has none,
+    /// None if it can't be computed
+    #[getter]
+    pub fn next_run<'p>(&self, py: Python<'p>) -> PyResult<Option<&'p PyDateTime>> {
+        self.runs_after(Utc::now(), 1, Tz::UTC)
+            .ok()
+            .and_then(|runs| runs.first().copied())
+            .map(|time| to_py_datetime_in(py, time, self.timezone.as_deref()))
+            .transpose()
+    }
+
     pub fn __str__(&self) -> PyResult<String> {
         Ok(format!(
             "Job ({}, {}, {}, {}, {}, {})",
             self.name,
-            self.schedule,
+            self.schedule_in_timezone(),
             self.command,
             self.source,
             self.active,
@@ -141,27 +228,38 @@ impl fmt::Display for Job {
         write!(
             f,
             "Job ({}, {}, {}, {})",
-            self.name, self.schedule, self.command, self.source
+            self.name,
+            self.schedule_in_timezone(),
+            self.command,
+            self.source
         )
     }
 }
 
 impl Job {
+    /// Check the job before it is added or synced, errors carry the job's name
     pub fn is_valid(&self) -> Result<(), ValidationError> {
+        self.check().map_err(|e| e.with_job(&self.name))
+    }
+
+    fn check(&self) -> Result<(), ValidationError> {
         if self.name.is_empty() {
             return Err("Name is empty".to_string().into());
         }
-        if !self.name.starts_with(PREFIX) {
-            return Err(format!("Name must start with {}", PREFIX).into());
+        if !self.name.starts_with(BASE_PREFIX) {
+            return Err(format!("Name must start with {}", BASE_PREFIX).into());
         }
         if self.schedule.is_empty() {
-            return Err("Schedule is empty".to_string().into());
+            return Err(ValidationError::invalid_schedule(
+                "Schedule is empty".to_string(),
+            ));
         }
         if self.command.is_empty() {
             return Err("Command is empty".to_string().into());
         }
 
-        schedule_is_valid(&self.schedule)?;
+        schedule_is_valid(&self.expanded_schedule()?)?;
+        self.tz()?;
 
         if self.command.contains("CALL") && self.source.is_empty() {
             return Err("Source is empty".to_string().into());
@@ -170,7 +268,69 @@ impl Job {
         Ok(())
     }
 
+    /// True if the job runs every n seconds, which needs pg_cron 1.5 or newer
+    pub fn has_interval_schedule(&self) -> bool {
+        let schedule = self.expanded_schedule().unwrap_or_default();
+        matches!(parse_schedule(&schedule), Ok(ParsedSchedule::Interval(_)))
+    }
+
     pub fn uses_stored_procedure(&self) -> bool {
         self.command.contains("CALL")
     }
+
+    /// Timezone the schedule is written in, UTC if the job has none
+    pub fn tz(&self) -> Result<Tz, ValidationError> {
+        self.tz_or(Tz::UTC)
+    }
+
+    /// Timezone the schedule is written in, `default` if the job has none
+    pub fn tz_or(&self, default: Tz) -> Result<Tz, ValidationError> {
+        self.timezone.as_deref().map_or(Ok(default), parse_timezone)
+    }
+
+    /// The schedule, with its expansion if it has `H` tokens, followed by its timezone
+    pub fn schedule_in_timezone(&self) -> String {
+        let schedule = match self.expanded_schedule() {
+            Ok(expanded) if expanded != self.schedule => {
+                format!("{} ({})", self.schedule, expanded)
+            }
+            _ => self.schedule.clone(),
+        };
+        match &self.timezone {
+            Some(timezone) => format!("{} {}", schedule, timezone),
+            None => schedule,
+        }
+    }
+
+    /// The next `n` times the job's schedule fires after `after`
+    /// A job without a timezone runs in `server`, the server's `cron.timezone`
+    pub fn runs_after(
+        &self,
+        after: DateTime<Utc>,
+        n: usize,
+        server: Tz,
+    ) -> Result<Vec<DateTime<Utc>>, ValidationError> {
+        let schedule = self.expanded_schedule()?;
+        next_runs(&schedule, after, n, self.last_run, self.tz_or(server)?)
+    }
+
+    /// The job with `H` tokens of its schedule replaced, as pg_cron gets it
+    pub fn with_expanded_schedule(&self) -> Result<Job, ValidationError> {
+        Ok(Job {
+            schedule: self.expanded_schedule()?,
+            ..self.clone()
+        })
+    }
+
+    /// Move the job, and the stored procedure it calls, to a namespace
+    pub fn in_namespace(&self, namespace: &Namespace) -> Job {
+        let name = namespace.qualify_name(&self.name);
+        let command = namespace.qualify_command(&self.command, &name);
+
+        Job {
+            name,
+            command,
+            ..self.clone()
+        }
+    }
 }
@@ -0,0 +1,86 @@
+//! Namespace
+//! Every job and stored procedure managed by a PgCronner instance is prefixed with its namespace,
+//! so instances with different namespaces never touch each other's objects.
+
+use crate::errors::ValidationError;
+use crate::utils::get_stored_procedure_name;
+use regex::Regex;
+
+/// Prefix shared by every namespace
+pub const BASE_PREFIX: &str = "pgcronner_";
+
+/// Prefix of the default namespace
+pub const DEFAULT_PREFIX: &str = "pgcronner__";
+
+/// A namespace, e.g. `billing` prefixes jobs with `pgcronner_billing__`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Namespace {
+    prefix: String,
+}
+
+impl Default for Namespace {
+    fn default() -> Self {
+        Self {
+            prefix: DEFAULT_PREFIX.to_string(),
+        }
+    }
+}
+
+impl Namespace {
+    /// Create a namespace
+    /// Names are lowercase alphanumeric words separated by single underscores,
+    /// so that no namespace's prefix is the prefix of another one.
+    pub fn new(name: &str) -> Result<Self, ValidationError> {
+        let re = Regex::new(r"^[a-z0-9]+(_[a-z0-9]+)*$").unwrap();
+        if !re.is_match(name) {
+            return Err(format!(
+                "Invalid namespace {}, use lowercase letters and digits separated by single underscores",
+                name
+            )
+            .into());
+        }
+        Ok(Self {
+            prefix: format!("{}{}__", BASE_PREFIX, name),
+        })
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// True if the job or procedure name belongs to this namespace
+    pub fn contains(&self, name: &str) -> bool {
+        name.starts_with(&self.prefix)
+    }
+
+    /// Add the namespace prefix to a name, replacing the default prefix if it has one
+    pub fn qualify_name(&self, name: &str) -> String {
+        if self.contains(name) {
+            return name.to_string();
+        }
+        let name = name.strip_prefix(DEFAULT_PREFIX).unwrap_or(name);
+        format!(
+            "{}{}",
+            self.prefix,
+            name.trim()
+                .replace(' ', "")
+                .to_lowercase()
+                .replace('-', "_")
+        )
+    }
+
+    /// Prefix the stored procedure called by a command, other commands are left as they are
+    pub fn qualify_command(&self, command: &str, name: &str) -> String {
+        match command.contains("CALL") {
+            true => {
+                let procedure = get_stored_procedure_name(command, name);
+                if self.contains(&procedure) {
+                    return format!("CALL {}();", procedure);
+                }
+                let procedure = procedure.strip_prefix(DEFAULT_PREFIX).unwrap_or(&procedure);
+                format!("CALL {}{}();", self.prefix, procedure)
+            }
+            false => command.to_string(),
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Connection pool
+//! Connections are opened when they are needed, up to the size of the pool, and reused.
+//! Connections that were idle for a while are checked before they are handed out, so a
+//! restarted server or an idle timeout only costs a reconnect. Reads that fail because their
+//! connection was lost are retried once on a new connection.
+//!
+//! The pool is fork safe: in a forked child, e.g. a gunicorn worker, connections inherited from
+//! the parent share the parent's sockets. They are left alone and the child opens its own.
+//!
+//! `AsyncPool` holds the tokio-postgres connections of AsyncPgCronner. Waiting for a connection
+//! or for a query doesn't block the event loop, connections that were closed are dropped.
+
+use log::{debug, warn};
+use postgres::{Client, Config, NoTls};
+use std::fmt::Display;
+use std::ops::{Deref, DerefMut};
+use std::process;
+use std::str::FromStr;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio_postgres::tls::TlsStream;
+use tokio_postgres::Connection;
+
+use crate::errors::{DbError, ErrorKind, ValidationError};
+use crate::tls::{make_tls_connector, parse_sslmode, SslMode, TlsOptions};
+
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+/// How long `get` waits for a connection when all of them are in use
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connections idle for longer than this are pinged before they are handed out
+const HEALTH_CHECK_AFTER: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The sslmode and the config of a uri, for postgres or tokio-postgres
+fn parse_uri<C>(uri: &str) -> Result<(SslMode, C), DbError>
+where
+    C: FromStr,
+    C::Err: Display,
+{
+    let (mode, uri) = parse_sslmode(uri).map_err(|e| DbError::new(e.into()))?;
+    let config = uri
+        .parse()
+        .map_err(|e| DbError::new(format!("Invalid DB Uri: {}", e)))?;
+    Ok((mode, config))
+}
+
+/// Connect to the database, with TLS if the uri's sslmode asks for it
+pub fn get_db_connection(uri: &str, tls: &TlsOptions) -> Result<Client, DbError> {
+    let (mode, config): (SslMode, Config) = parse_uri(uri)?;
+
+    let client = match mode {
+        SslMode::Disable => config.connect(NoTls),
+        _ => config.connect(make_tls_connector(mode, tls)?),
+    }
+    .map_err(|e| {
+        DbError::postgres("Could not connect to DB", &e).with_kind(ErrorKind::Connection)
+    })?;
+    debug!("Connected to DB with sslmode {:?}", mode);
+    Ok(client)
+}
+
+struct Idle {
+    client: Client,
+    since: Instant,
+}
+
+struct State {
+    idle: Vec<Idle>,
+    /// Connections idle or handed out
+    open: usize,
+    /// Process the connections belong to
+    pid: u32,
+}
+
+/// A pool of connections to one database, safe to share between threads
+///
+/// # Arguments
+/// * `uri` - The database uri
+/// * `tls` - Certificates for TLS connections
+/// * `size` - Maximum number of open connections
+///
+pub struct Pool {
+    uri: String,
+    tls: TlsOptions,
+    size: usize,
+    timeout: Duration,
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+impl Pool {
+    pub fn new(uri: &str, tls: TlsOptions, size: usize) -> Result<Self, ValidationError> {
+        if size < 1 {
+            return Err("pool_size must be at least 1".to_string().into());
+        }
+        Ok(Self {
+            uri: uri.to_string(),
+            tls,
+            size,
+            timeout: CHECKOUT_TIMEOUT,
+            state: Mutex::new(State {
+                idle: Vec::new(),
+                open: 0,
+                pid: process::id(),
+            }),
+            released: Condvar::new(),
+        })
+    }
+
+    /// Wait at most `timeout` for a connection when all of them are in use
+    #[cfg(test)]
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of open connections, idle or in use
+    #[cfg(test)]
+    pub fn open(&self) -> usize {
+        self.lock().open
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic while holding the lock leaves the state consistent, every change is one step
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.pid != process::id() {
+            forget_inherited(&mut state);
+        }
+        state
+    }
+
+    /// Forget a connection that was closed or failed its health check
+    fn discard(&self) {
+        self.lock().open -= 1;
+        self.released.notify_one();
+    }
+
+    /// Get a connection, opening one if none is idle and the pool isn't full,
+    /// otherwise wait for one to be released
+    pub fn get(&self) -> Result<PooledClient<'_>, DbError> {
+        let deadline = Instant::now() + self.timeout;
+        let mut state = self.lock();
+        loop {
+            if let Some(idle) = state.idle.pop() {
+                drop(state);
+                if let Some(client) = self.check(idle) {
+                    return Ok(PooledClient {
+                        pool: self,
+                        client: Some(client),
+                        pid: process::id(),
+                    });
+                }
+                state = self.lock();
+                continue;
+            }
+            if state.open < self.size {
+                state.open += 1;
+                drop(state);
+                return match get_db_connection(&self.uri, &self.tls) {
+                    Ok(client) => Ok(PooledClient {
+                        pool: self,
+                        client: Some(client),
+                        pid: process::id(),
+                    }),
+                    Err(e) => {
+                        self.discard();
+                        Err(e)
+                    }
+                };
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(DbError::new(format!(
+                    "Timed out after {:?} waiting for one of the {} connections of the pool",
+                    self.timeout, self.size
+                ))
+                .with_kind(ErrorKind::Connection));
+            }
+            state = self
+                .released
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// The idle connection if it still works, None after discarding it
+    fn check(&self, idle: Idle) -> Option<Client> {
+        let Idle { mut client, since } = idle;
+        let healthy = !client.is_closed()
+            && (since.elapsed() < HEALTH_CHECK_AFTER
+                || client.is_valid(HEALTH_CHECK_TIMEOUT).is_ok());
+        if healthy {
+            return Some(client);
+        }
+        warn!("Dropping broken connection from the pool");
+        self.discard();
+        None
+    }
+
+    /// Run a read on a connection of the pool, retried once on a new connection
+    /// if the connection was lost, e.g. after a server restart or an idle timeout.
+    /// Only for reads, or statements that can safely run twice.
+    pub fn read<T, E: From<DbError>>(
+        &self,
+        mut f: impl FnMut(&mut Client) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut client = self.get()?;
+        match f(&mut client) {
+            Err(_) if client.is_closed() => {
+                warn!("Lost connection to DB, retrying on a new connection");
+                drop(client);
+                f(&mut *self.get()?)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Forget the connections of the parent in a forked child
+/// Dropping them would tell the server to close the parent's sessions, so they are leaked
+fn forget_inherited(state: &mut State) {
+    debug!(
+        "Process {} was forked from {}, opening new connections",
+        process::id(),
+        state.pid
+    );
+    for idle in state.idle.drain(..) {
+        std::mem::forget(idle.client);
+    }
+    state.open = 0;
+    state.pid = process::id();
+}
+
+/// A connection of the pool, given back to it when dropped
+pub struct PooledClient<'a> {
+    pool: &'a Pool,
+    client: Option<Client>,
+    /// Process the connection was opened or handed out in
+    pid: u32,
+}
+
+impl Deref for PooledClient<'_> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledClient<'_> {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledClient<'_> {
+    fn drop(&mut self) {
+        let Some(client) = self.client.take() else {
+            return;
+        };
+        if self.pid != process::id() {
+            // Handed out before a fork, it belongs to the parent
+            std::mem::forget(client);
+            return;
+        }
+        if client.is_closed() {
+            self.pool.discard();
+            return;
+        }
+        self.pool.lock().idle.push(Idle {
+            client,
+            since: Instant::now(),
+        });
+        self.pool.released.notify_one();
+    }
+}
+
+/// Connect to the database without blocking, with TLS if the uri's sslmode asks for it
+/// The connection is driven by a task of the tokio runtime the call runs on
+pub async fn get_async_connection(
+    uri: &str,
+    tls: &TlsOptions,
+) -> Result<tokio_postgres::Client, DbError> {
+    let (mode, config): (SslMode, tokio_postgres::Config) = parse_uri(uri)?;
+
+    let client = match mode {
+        SslMode::Disable => config
+            .connect(tokio_postgres::NoTls)
+            .await
+            .map(spawn_connection),
+        _ => config
+            .connect(make_tls_connector(mode, tls)?)
+            .await
+            .map(spawn_connection),
+    }
+    .map_err(|e| {
+        DbError::postgres("Could not connect to DB", &e).with_kind(ErrorKind::Connection)
+    })?;
+    debug!("Connected to DB with sslmode {:?}", mode);
+    Ok(client)
+}
+
+/// Run the connection of a client until it is closed
+/// Its errors fail the queries of the client, which the pool then drops. They aren't logged:
+/// the task may end while the interpreter shuts down, when Python's logging can't be called
+fn spawn_connection<S, T>(
+    (client, connection): (tokio_postgres::Client, Connection<S, T>),
+) -> tokio_postgres::Client
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: TlsStream + Unpin + Send + 'static,
+{
+    tokio::spawn(connection);
+    client
+}
+
+/// A pool of tokio-postgres connections to one database, for AsyncPgCronner
+///
+/// # Arguments
+/// * `uri` - The database uri
+/// * `tls` - Certificates for TLS connections
+/// * `size` - Maximum number of open connections
+///
+pub struct AsyncPool {
+    uri: String,
+    tls: TlsOptions,
+    size: usize,
+    timeout: Duration,
+    idle: Mutex<Vec<tokio_postgres::Client>>,
+    /// One permit per connection that can be handed out
+    permits: Semaphore,
+}
+
+impl AsyncPool {
+    pub fn new(uri: &str, tls: TlsOptions, size: usize) -> Result<Self, ValidationError> {
+        if size < 1 {
+            return Err("pool_size must be at least 1".to_string().into());
+        }
+        Ok(Self {
+            uri: uri.to_string(),
+            tls,
+            size,
+            timeout: CHECKOUT_TIMEOUT,
+            idle: Mutex::new(Vec::new()),
+            permits: Semaphore::new(size),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<tokio_postgres::Client>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get a connection, opening one if none is idle,
+    /// otherwise wait for one to be released
+    pub async fn get(&self) -> Result<AsyncPooledClient<'_>, DbError> {
+        let permit = match tokio::time::timeout(self.timeout, self.permits.acquire()).await {
+            Ok(permit) => permit.expect("the semaphore of the pool is never closed"),
+            Err(_) => {
+                return Err(DbError::new(format!(
+                    "Timed out after {:?} waiting for one of the {} connections of the pool",
+                    self.timeout, self.size
+                ))
+                .with_kind(ErrorKind::Connection))
+            }
+        };
+        loop {
+            let Some(client) = self.lock().pop() else {
+                break;
+            };
+            if !client.is_closed() {
+                return Ok(AsyncPooledClient {
+                    pool: self,
+                    client: Some(client),
+                    _permit: permit,
+                });
+            }
+            warn!("Dropping broken connection from the pool");
+        }
+        Ok(AsyncPooledClient {
+            pool: self,
+            client: Some(get_async_connection(&self.uri, &self.tls).await?),
+            _permit: permit,
+        })
+    }
+}
+
+/// A connection of the async pool, given back to it when dropped
+pub struct AsyncPooledClient<'a> {
+    pool: &'a AsyncPool,
+    client: Option<tokio_postgres::Client>,
+    /// Released after the connection is back in the pool
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Deref for AsyncPooledClient<'_> {
+    type Target = tokio_postgres::Client;
+
+    fn deref(&self) -> &tokio_postgres::Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for AsyncPooledClient<'_> {
+    fn deref_mut(&mut self) -> &mut tokio_postgres::Client {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl Drop for AsyncPooledClient<'_> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take().filter(|client| !client.is_closed()) {
+            self.pool.lock().push(client);
+        }
+    }
+}
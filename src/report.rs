@@ -0,0 +1,222 @@
+//! Sync report
+//! What a sync did for each job
+
+use pyo3::prelude::*;
+use std::fmt;
+use std::time::Duration;
+
+/// Outcome of a sync for a single job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Job was added to `cron.job`
+    Scheduled,
+    /// Job was already in `cron.job` and has been altered
+    Updated,
+    /// Job was already up to date
+    Unchanged,
+    /// Job is kept in `cron.job` with `active = false`
+    Disabled,
+    /// Job was removed from `cron.job`
+    Unscheduled,
+    /// Stored procedure no job calls anymore was dropped
+    ProcedureDropped,
+    /// Row could not be read or the job is not valid, `cron.job` is left as it was
+    SkippedInvalid,
+    /// The job's stored procedure could not be created
+    ProcedureFailed,
+    /// `cron.job` could not be changed for the job
+    ScheduleFailed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Scheduled => "scheduled",
+            JobStatus::Updated => "updated",
+            JobStatus::Unchanged => "unchanged",
+            JobStatus::Disabled => "disabled",
+            JobStatus::Unscheduled => "unscheduled",
+            JobStatus::ProcedureDropped => "procedure_dropped",
+            JobStatus::SkippedInvalid => "skipped_invalid",
+            JobStatus::ProcedureFailed => "procedure_failed",
+            JobStatus::ScheduleFailed => "schedule_failed",
+        }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::SkippedInvalid | JobStatus::ProcedureFailed | JobStatus::ScheduleFailed
+        )
+    }
+
+    /// True if the job is in `cron.job` once the sync is done
+    pub fn is_scheduled(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Scheduled | JobStatus::Updated | JobStatus::Unchanged | JobStatus::Disabled
+        )
+    }
+}
+
+/// Result of a sync for a single job
+///
+/// # Arguments
+/// * `job` - Name of the job, or of the stored procedure for procedure_dropped
+/// * `status` - One of scheduled, updated, unchanged, disabled, unscheduled, procedure_dropped,
+///   skipped_invalid, procedure_failed, schedule_failed
+/// * `error` - The underlying error message, if the job failed
+///
+#[derive(Debug, Clone)]
+#[pyclass(module = "pgcronner")]
+pub struct JobSyncResult {
+    #[pyo3(get)]
+    pub job: String,
+    pub status: JobStatus,
+    #[pyo3(get)]
+    pub error: Option<String>,
+}
+
+#[pymethods]
+impl JobSyncResult {
+    #[getter]
+    fn status(&self) -> &'static str {
+        self.status.as_str()
+    }
+
+    pub fn __str__(&self) -> PyResult<String> {
+        Ok(self.to_string())
+    }
+
+    pub fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "JobSyncResult(job={}, status={}, error={:?})",
+            self.job,
+            self.status.as_str(),
+            self.error
+        ))
+    }
+}
+
+impl fmt::Display for JobSyncResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.job, self.status.as_str())?;
+        if let Some(error) = &self.error {
+            write!(f, " ({})", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a sync did, one result per job
+#[derive(Debug, Clone, Default)]
+#[pyclass(module = "pgcronner")]
+pub struct SyncReport {
+    #[pyo3(get)]
+    pub jobs: Vec<JobSyncResult>,
+    pub elapsed: Duration,
+}
+
+impl SyncReport {
+    /// Record the outcome of an action, the first failure of a job wins
+    pub fn record(&mut self, job: &str, status: JobStatus, error: Option<String>) {
+        match self.jobs.iter_mut().find(|result| result.job == job) {
+            Some(result) if result.status.is_failure() => {}
+            Some(result)
+                if status.is_failure()
+                    || status == JobStatus::Updated
+                    || status == JobStatus::Disabled =>
+            {
+                result.status = status;
+                result.error = error;
+            }
+            Some(_) => {}
+            None => self.jobs.push(JobSyncResult {
+                job: job.to_string(),
+                status,
+                error,
+            }),
+        }
+    }
+
+    fn count(&self, predicate: impl Fn(&JobStatus) -> bool) -> usize {
+        self.jobs
+            .iter()
+            .filter(|result| predicate(&result.status))
+            .count()
+    }
+}
+
+#[pymethods]
+impl SyncReport {
+    /// Number of jobs in `cron.job` after the sync
+    #[getter]
+    pub fn scheduled(&self) -> usize {
+        self.count(JobStatus::is_scheduled)
+    }
+
+    /// Number of jobs removed from `cron.job`
+    #[getter]
+    pub fn unscheduled(&self) -> usize {
+        self.count(|status| *status == JobStatus::Unscheduled)
+    }
+
+    /// Number of jobs that were skipped or failed
+    #[getter]
+    pub fn failed(&self) -> usize {
+        self.count(JobStatus::is_failure)
+    }
+
+    /// Number of jobs in the report
+    #[getter]
+    pub fn total(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// Duration of the sync in seconds
+    #[getter]
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed.as_secs_f64()
+    }
+
+    /// Results of the jobs that were skipped or failed
+    pub fn failures(&self) -> Vec<JobSyncResult> {
+        self.jobs
+            .iter()
+            .filter(|result| result.status.is_failure())
+            .cloned()
+            .collect()
+    }
+
+    pub fn __str__(&self) -> PyResult<String> {
+        Ok(self.to_string())
+    }
+
+    pub fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "SyncReport(total={}, scheduled={}, unscheduled={}, failed={}, elapsed={:.3}s)",
+            self.total(),
+            self.scheduled(),
+            self.unscheduled(),
+            self.failed(),
+            self.elapsed()
+        ))
+    }
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} jobs scheduled, {} unscheduled, {} failed in {:.3}s",
+            self.scheduled(),
+            self.unscheduled(),
+            self.failed(),
+            self.elapsed()
+        )?;
+        for result in &self.jobs {
+            writeln!(f, "  {}", result)?;
+        }
+        Ok(())
+    }
+}
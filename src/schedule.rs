@@ -0,0 +1,1003 @@
+//! Schedule
+//! Parses schedules with pg_cron's grammar and computes when they fire
+//!
+//! pg_cron accepts:
+//! * 5 cron fields, minute hour day-of-month month day-of-week, each a comma separated list
+//!   of `*`, numbers, names (`jan`-`dec`, `sun`-`sat`) or ranges, optionally with a `/step`
+//! * `$` in the day-of-month field for the last day of the month
+//! * the macros `@yearly`, `@annually`, `@monthly`, `@weekly`, `@daily`, `@midnight`,
+//!   `@hourly` and `@reboot`
+//! * intervals of 1 to 59 seconds, `'30 seconds'`
+//!
+//! Vixie cron, which pg_cron's parser comes from, ignores what follows a list up to the next
+//! blank, so a step on a single value is dropped (`5/10` is read as `5`), and backwards ranges
+//! such as `30-10` set nothing. Both are accepted and read the same way here, every schedule
+//! pg_cron takes is valid.
+
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+use pyo3::basic::CompareOp;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::fmt;
+use std::sync::LazyLock;
+
+use crate::describe::{describe_schedule, parse_time, parse_weekday, schedule_from_text};
+use crate::errors::ValidationError;
+use crate::job::Job;
+
+/// Macros and the schedule they stand for, `@reboot` aside
+pub const MACROS: [(&str, &str); 7] = [
+    ("@yearly", "0 0 1 1 *"),
+    ("@annually", "0 0 1 1 *"),
+    ("@monthly", "0 0 1 * *"),
+    ("@weekly", "0 0 * * 0"),
+    ("@daily", "0 0 * * *"),
+    ("@midnight", "0 0 * * *"),
+    ("@hourly", "0 * * * *"),
+];
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Runs are looked for this many days ahead, enough to reach the next 29th of February
+const SEARCH_DAYS: i64 = 366 * 8 + 1;
+/// Runs listed by `upcoming` unless asked otherwise, a `1 second` job fires 3600 times an hour
+pub const DEFAULT_UPCOMING_LIMIT: usize = 500;
+
+/// Error in a schedule, `position` is the 0-based character offset of the offending token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleError {
+    pub schedule: String,
+    pub position: usize,
+    pub message: String,
+}
+
+impl std::error::Error for ScheduleError {}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid schedule \"{}\" at position {}: {}",
+            self.schedule, self.position, self.message
+        )
+    }
+}
+
+impl From<ScheduleError> for ValidationError {
+    fn from(error: ScheduleError) -> ValidationError {
+        ValidationError::invalid_schedule(error.to_string())
+    }
+}
+
+/// The 5 fields of a cron schedule, as bitsets of the values they match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronFields {
+    pub minutes: u64,
+    pub hours: u64,
+    pub days: u64,
+    pub last_day: bool,
+    pub months: u64,
+    pub weekdays: u64,
+    // Like cron, day-of-month and day-of-week are ORed unless one of them starts with `*`
+    pub(crate) days_star: bool,
+    pub(crate) weekdays_star: bool,
+}
+
+/// True if a field's bitset has every value from `low` to `high`
+pub fn is_full(bits: u64, low: u32, high: u32) -> bool {
+    (low..=high).all(|value| bits & 1 << value != 0)
+}
+
+/// A parsed schedule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsedSchedule {
+    Cron(CronFields),
+    /// Every n seconds
+    Interval(u32),
+    /// When pg_cron starts
+    Reboot,
+}
+
+/// A cron field, with the range of its values and their names
+struct Field {
+    name: &'static str,
+    low: u32,
+    high: u32,
+    names: &'static [&'static str],
+}
+
+const FIELDS: [Field; 5] = [
+    Field {
+        name: "minute",
+        low: 0,
+        high: 59,
+        names: &[],
+    },
+    Field {
+        name: "hour",
+        low: 0,
+        high: 23,
+        names: &[],
+    },
+    Field {
+        name: "day of month",
+        low: 1,
+        high: 31,
+        names: &[],
+    },
+    Field {
+        name: "month",
+        low: 1,
+        high: 12,
+        names: &MONTHS,
+    },
+    Field {
+        name: "day of week",
+        low: 0,
+        high: 7,
+        names: &WEEKDAYS,
+    },
+];
+
+struct Parser<'a> {
+    schedule: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(schedule: &'a str) -> Self {
+        Self {
+            schedule,
+            chars: schedule.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error(&self, position: usize, message: String) -> ScheduleError {
+        ScheduleError {
+            schedule: self.schedule.to_string(),
+            position,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_blanks(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn at_blank_or_end(&self) -> bool {
+        self.peek().is_none_or(char::is_whitespace)
+    }
+
+    /// Collect a run of letters and digits, like cron's get_number
+    fn token(&mut self) -> (usize, String) {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            self.pos += 1;
+        }
+        (start, self.chars[start..self.pos].iter().collect())
+    }
+
+    fn macro_schedule(&mut self) -> Result<ParsedSchedule, ScheduleError> {
+        let start = self.pos;
+        while !self.at_blank_or_end() {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        self.skip_blanks();
+        if self.peek().is_some() {
+            return Err(self.error(self.pos, format!("unexpected characters after {}", name)));
+        }
+
+        if name == "@reboot" {
+            return Ok(ParsedSchedule::Reboot);
+        }
+        match MACROS.iter().find(|(macro_name, _)| *macro_name == name) {
+            Some((_, schedule)) => parse_schedule(schedule),
+            None => Err(self.error(
+                start,
+                format!(
+                    "unknown macro {}, use one of {}, @reboot",
+                    name,
+                    MACROS.map(|(name, _)| name).join(", ")
+                ),
+            )),
+        }
+    }
+
+    fn cron_schedule(&mut self) -> Result<ParsedSchedule, ScheduleError> {
+        let mut bits = [0u64; 5];
+        let mut stars = [false; 5];
+        let mut last_day = false;
+
+        for (i, field) in FIELDS.iter().enumerate() {
+            self.skip_blanks();
+            if self.peek().is_none() {
+                return Err(self.error(
+                    self.pos,
+                    format!("missing {} field, schedules have 5 fields", field.name),
+                ));
+            }
+            stars[i] = self.peek() == Some('*');
+            self.list(field, &mut bits[i], &mut last_day)?;
+            // Like cron's get_list, the rest of the field is skipped, e.g. the `/10` of `5/10`
+            while !self.at_blank_or_end() {
+                self.pos += 1;
+            }
+        }
+
+        self.skip_blanks();
+        if self.peek().is_some() {
+            return Err(self.error(
+                self.pos,
+                "unexpected characters after the day of week field, schedules have 5 fields"
+                    .to_string(),
+            ));
+        }
+
+        // 0 and 7 are both Sunday
+        if bits[4] & (1 | 1 << 7) != 0 {
+            bits[4] |= 1 | 1 << 7;
+        }
+
+        Ok(ParsedSchedule::Cron(CronFields {
+            minutes: bits[0],
+            hours: bits[1],
+            days: bits[2],
+            last_day,
+            months: bits[3],
+            weekdays: bits[4],
+            days_star: stars[2],
+            weekdays_star: stars[4],
+        }))
+    }
+
+    fn list(
+        &mut self,
+        field: &Field,
+        bits: &mut u64,
+        last_day: &mut bool,
+    ) -> Result<(), ScheduleError> {
+        loop {
+            self.range(field, bits, last_day)?;
+            if self.peek() != Some(',') {
+                return Ok(());
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn range(
+        &mut self,
+        field: &Field,
+        bits: &mut u64,
+        last_day: &mut bool,
+    ) -> Result<(), ScheduleError> {
+        let (low, high) = match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                (field.low, field.high)
+            }
+            Some('$') if field.low == 1 && field.high == 31 => {
+                self.pos += 1;
+                if matches!(self.peek(), Some('-') | Some('/')) {
+                    return Err(self.error(
+                        self.pos,
+                        "$ can't be part of a range or have a step".to_string(),
+                    ));
+                }
+                *last_day = true;
+                return Ok(());
+            }
+            _ => {
+                let low = self.value(field)?;
+                if self.peek() != Some('-') {
+                    *bits |= 1 << low;
+                    return Ok(());
+                }
+                self.pos += 1;
+                // A backwards range sets nothing, like in cron
+                (low, self.value(field)?)
+            }
+        };
+
+        let mut step = 1;
+        if self.peek() == Some('/') {
+            self.pos += 1;
+            let (start, token) = self.token();
+            step = match token.parse::<u32>() {
+                Ok(0) => return Err(self.error(start, "step must be at least 1".to_string())),
+                Ok(step) => step,
+                Err(_) if token.is_empty() => {
+                    return Err(self.error(start, "expected a step after /".to_string()))
+                }
+                Err(_) => {
+                    return Err(self.error(start, format!("expected a step, found '{}'", token)))
+                }
+            };
+        }
+
+        for value in (low..=high).step_by(step as usize) {
+            *bits |= 1 << value;
+        }
+        Ok(())
+    }
+
+    /// A number or a name within the field's range
+    fn value(&mut self, field: &Field) -> Result<u32, ScheduleError> {
+        let (start, token) = self.token();
+        if token.is_empty() {
+            let found = self
+                .peek()
+                .map_or("end of schedule".to_string(), |c| format!("'{}'", c));
+            return Err(self.error(
+                start,
+                format!("expected a {} value, found {}", field.name, found),
+            ));
+        }
+
+        if let Some(index) = field
+            .names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(&token))
+        {
+            return Ok(index as u32 + field.low);
+        }
+        if !token.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(self.error(start, format!("invalid {} '{}'", field.name, token)));
+        }
+        match token.parse::<u32>() {
+            Ok(value) if (field.low..=field.high).contains(&value) => Ok(value),
+            _ => Err(self.error(
+                start,
+                format!(
+                    "{} {} is out of range {}-{}",
+                    field.name, token, field.low, field.high
+                ),
+            )),
+        }
+    }
+}
+
+// pg_cron reads intervals with sscanf(" %u secon%c%c %c"), "second" and "seconds" are both fine
+static INTERVAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^\s*(\d+)\s*seconds?\s*$").unwrap());
+static HASHED_TOKEN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^H(?:\((\d+)-(\d+)\))?(?:/(\d+))?$").unwrap());
+static WORD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\S+").unwrap());
+
+/// Parse a schedule with pg_cron's grammar
+pub fn parse_schedule(schedule: &str) -> Result<ParsedSchedule, ScheduleError> {
+    let mut parser = Parser::new(schedule);
+    parser.skip_blanks();
+    if parser.peek().is_none() {
+        return Err(parser.error(0, "schedule is empty".to_string()));
+    }
+    if parser.peek() == Some('@') {
+        return parser.macro_schedule();
+    }
+
+    if let Some(caps) = INTERVAL.captures(schedule) {
+        let seconds = caps.get(1).unwrap();
+        let position = schedule[..seconds.start()].chars().count();
+        return match seconds.as_str().parse::<u32>() {
+            Ok(seconds) if (1..=59).contains(&seconds) => Ok(ParsedSchedule::Interval(seconds)),
+            _ => Err(parser.error(
+                position,
+                "interval must be between 1 and 59 seconds".to_string(),
+            )),
+        };
+    }
+    if schedule.to_lowercase().contains("second") {
+        return Err(parser.error(
+            parser.pos,
+            "expected an interval of 1 to 59 seconds, e.g. '30 seconds'".to_string(),
+        ));
+    }
+
+    parser.cron_schedule()
+}
+
+/// Stable hash of a job name for one field, the same on every platform and release
+fn field_hash(seed: &str, field: usize) -> u64 {
+    // FNV-1a, then a finalizer so the low bits used by `%` are well mixed
+    let mut hash = seed
+        .bytes()
+        .chain([field as u8])
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^ hash >> 33
+}
+
+/// True for an item of a field written as an `H` token, a bare `H` or `H` followed by `(` or `/`
+/// Names with an `H`, such as `THU`, are not
+fn is_hashed_token(token: &str) -> bool {
+    token == "H" || token.starts_with("H(") || token.starts_with("H/")
+}
+
+/// True if the schedule has Jenkins style `H` tokens, see `expand_hashed`
+pub fn is_hashed(schedule: &str) -> bool {
+    WORD.find_iter(schedule)
+        .any(|word| word.as_str().split(',').any(is_hashed_token))
+}
+
+/// Value of one `H` token of field `i`
+fn expand_hashed_token(token: &str, i: usize, seed: &str) -> Result<String, String> {
+    let field = &FIELDS[i];
+    let caps = HASHED_TOKEN.captures(token).ok_or_else(|| {
+        format!(
+            "expected H, H(a-b), H/n or H(a-b)/n in {} field",
+            field.name
+        )
+    })?;
+    // Numbers too large for u32 are out of range anyway
+    let number = |i: usize| {
+        caps.get(i)
+            .map(|m| m.as_str().parse::<u32>().unwrap_or(u32::MAX))
+    };
+
+    // Sunday is only 0, days of month stop at 28 unless a range says otherwise
+    let max = if i == 4 { 6 } else { field.high };
+    let (low, high) = match (number(1), number(2)) {
+        (Some(low), Some(high)) if field.low <= low && low <= high && high <= max => (low, high),
+        (Some(low), Some(high)) => {
+            return Err(format!(
+                "H range {}-{} is out of range {}-{} for {}",
+                low, high, field.low, max, field.name
+            ))
+        }
+        _ if i == 2 => (1, 28),
+        _ => (field.low, max),
+    };
+
+    let span = high - low + 1;
+    let hash = field_hash(seed, i);
+    match number(3) {
+        None => Ok((low as u64 + hash % span as u64).to_string()),
+        Some(step) if (1..=span).contains(&step) => {
+            let first = low as u64 + hash % step as u64;
+            Ok(format!("{}-{}/{}", first, high, step))
+        }
+        Some(step) => Err(format!(
+            "H step {} is out of range 1-{} for {}",
+            step, span, field.name
+        )),
+    }
+}
+
+/// Replace Jenkins style `H` tokens with values derived from a hash of `seed`, the job name,
+/// so jobs written `H * * * *` don't all run at the top of the hour
+///
+/// * `H` - One value of the field, days of month are picked from 1-28 so every month has them
+/// * `H(a-b)` - One value from `a` to `b`
+/// * `H/n` - Every `n` from a start below `n`, e.g. `H/15` may give `7-59/15`
+/// * `H(a-b)/n` - Every `n` from `a` to `b`
+///
+/// # Returns
+/// The schedule in pg_cron syntax, the schedule as it is if it has no `H`
+pub fn expand_hashed(schedule: &str, seed: &str) -> Result<String, ScheduleError> {
+    if !is_hashed(schedule) {
+        return Ok(schedule.to_string());
+    }
+    let error = |start: usize, message: String| ScheduleError {
+        schedule: schedule.to_string(),
+        position: schedule[..start].chars().count(),
+        message,
+    };
+    let words: Vec<_> = WORD.find_iter(schedule).collect();
+    if words.len() != FIELDS.len() {
+        let start = words
+            .iter()
+            .find(|word| word.as_str().split(',').any(is_hashed_token))
+            .map_or(0, |word| word.start());
+        return Err(error(
+            start,
+            "H is only allowed in the 5 cron fields".into(),
+        ));
+    }
+
+    let mut expanded = schedule.to_string();
+    let mut replacements = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        let mut start = word.start();
+        for token in word.as_str().split(',') {
+            if is_hashed_token(token) {
+                let value = expand_hashed_token(token, i, seed).map_err(|e| error(start, e))?;
+                replacements.push((start..start + token.len(), value));
+            }
+            start += token.len() + 1;
+        }
+    }
+    // From the end, so the offsets of earlier tokens stay valid
+    for (range, value) in replacements.into_iter().rev() {
+        expanded.replace_range(range, &value);
+    }
+    Ok(expanded)
+}
+
+impl CronFields {
+    /// True if a field has no values left, e.g. after a backwards range such as `30-10`
+    pub fn never_fires(&self) -> bool {
+        let days = self.days != 0 || self.last_day;
+        let weekdays = self.weekdays != 0;
+        let any_day = match self.days_star || self.weekdays_star {
+            true => days && weekdays,
+            false => days || weekdays,
+        };
+        self.minutes == 0 || self.hours == 0 || self.months == 0 || !any_day
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if self.months & 1 << date.month() == 0 {
+            return false;
+        }
+        let last_day = self.last_day && (date + Duration::days(1)).day() == 1;
+        let day = self.days & 1 << date.day() != 0 || last_day;
+        let weekday = self.weekdays & 1 << date.weekday().num_days_from_sunday() != 0;
+        if self.days_star || self.weekdays_star {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    /// First local time the fields match strictly after `after`, None if they never do
+    fn next_local(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let first_day = start.date();
+        (0..SEARCH_DAYS)
+            .map(|offset| first_day + Duration::days(offset))
+            .filter(|date| self.matches_day(*date))
+            .find_map(|date| {
+                let from = if date == first_day {
+                    start.hour() * 60 + start.minute()
+                } else {
+                    0
+                };
+                (from..24 * 60)
+                    .find(|minute| {
+                        self.hours & 1 << (minute / 60) != 0
+                            && self.minutes & 1 << (minute % 60) != 0
+                    })
+                    .and_then(|minute| date.and_hms_opt(minute / 60, minute % 60, 0))
+            })
+    }
+
+    /// First time the fields match strictly after `after`, read as local times of `tz`,
+    /// None if they never do
+    pub fn next_after(&self, after: DateTime<Utc>, tz: Tz) -> Option<DateTime<Utc>> {
+        let mut local = after.with_timezone(&tz).naive_local();
+        loop {
+            local = self.next_local(local)?;
+            // Local times skipped by a DST change don't fire, repeated ones fire the first time
+            match tz.from_local_datetime(&local) {
+                LocalResult::Single(time) | LocalResult::Ambiguous(time, _)
+                    if time.with_timezone(&Utc) > after =>
+                {
+                    return Some(time.with_timezone(&Utc))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl ParsedSchedule {
+    /// One of cron, interval or reboot
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParsedSchedule::Cron(_) => "cron",
+            ParsedSchedule::Interval(_) => "interval",
+            ParsedSchedule::Reboot => "reboot",
+        }
+    }
+
+    /// Next time the schedule fires strictly after `after`, None if it never does
+    ///
+    /// pg_cron doesn't align interval schedules to the clock, they fire every n seconds
+    /// counting from `anchor`, the last run of the job, or from `after` when it never ran.
+    /// Cron schedules are read as local times of `tz`
+    pub fn next_after(
+        &self,
+        after: DateTime<Utc>,
+        anchor: Option<DateTime<Utc>>,
+        tz: Tz,
+    ) -> Option<DateTime<Utc>> {
+        match self {
+            ParsedSchedule::Cron(fields) => fields.next_after(after, tz),
+            ParsedSchedule::Interval(seconds) => {
+                let anchor = anchor.unwrap_or(after);
+                let interval = *seconds as i64 * 1_000_000;
+                let elapsed = (after - anchor).num_microseconds()?;
+                let intervals = elapsed.div_euclid(interval) + 1;
+                Some(anchor + Duration::microseconds(intervals * interval))
+            }
+            ParsedSchedule::Reboot => None,
+        }
+    }
+}
+
+/// A pg_cron schedule, checked when it is created
+///
+/// # Arguments
+/// * `expression` - The schedule in pg_cron syntax
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[pyclass(module = "pgcronner")]
+pub struct Schedule {
+    expression: String,
+}
+
+impl Schedule {
+    pub fn new(expression: &str) -> Result<Self, ValidationError> {
+        // `H` is resolved per job, any name tells whether the schedule is valid
+        parse_schedule(&expand_hashed(expression, "")?)?;
+        Ok(Self {
+            expression: normalize_schedule(expression),
+        })
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+}
+
+#[pymethods]
+impl Schedule {
+    /// Create a schedule from pg_cron syntax
+    ///
+    /// # Example
+    /// ```
+    /// schedule = Schedule("*/15 9-17 * * 1-5")
+    /// ```
+    #[new]
+    #[pyo3(text_signature = "(expression)")]
+    fn py_new(expression: &str) -> Result<Self, ValidationError> {
+        Schedule::new(expression)
+    }
+
+    /// Read a schedule written in English
+    ///
+    /// # Example
+    /// ```
+    /// schedule = Schedule.from_text("every day at 3am")
+    /// str(schedule)  # "0 3 * * *"
+    /// ```
+    #[staticmethod]
+    #[pyo3(text_signature = "(text)")]
+    pub fn from_text(text: &str) -> Result<Self, ValidationError> {
+        let expression =
+            schedule_from_text(text).map_err(|e| ValidationError::invalid_schedule(e.into()))?;
+        Schedule::new(&expression)
+    }
+
+    /// Schedule written in pg_cron syntax, same as `Schedule(expression)`
+    ///
+    /// # Example
+    /// ```
+    /// schedule = Schedule.cron("0 12 $ * *")
+    /// ```
+    #[staticmethod]
+    #[pyo3(text_signature = "(expression)")]
+    pub fn cron(expression: &str) -> Result<Self, ValidationError> {
+        Schedule::new(expression)
+    }
+
+    /// Every `seconds` seconds, 1 to 59, needs pg_cron 1.5 or newer
+    ///
+    /// # Example
+    /// ```
+    /// Schedule.every_seconds(30)  # Schedule('30 seconds')
+    /// ```
+    #[staticmethod]
+    #[pyo3(text_signature = "(seconds)")]
+    pub fn every_seconds(seconds: u32) -> Result<Self, ValidationError> {
+        check_step("seconds", seconds, 59)?;
+        Schedule::new(&format!("{} seconds", seconds))
+    }
+
+    /// Every `minutes` minutes from the start of the hour, 1 to 59
+    ///
+    /// # Example
+    /// ```
+    /// Schedule.every_minutes(5)  # Schedule('*/5 * * * *')
+    /// ```
+    #[staticmethod]
+    #[pyo3(text_signature = "(minutes)")]
+    pub fn every_minutes(minutes: u32) -> Result<Self, ValidationError> {
+        check_step("minutes", minutes, 59)?;
+        Schedule::new(&format!("{} * * * *", step_field(minutes)))
+    }
+
+    /// Every `hours` hours from midnight, on the hour, 1 to 23
+    ///
+    /// # Example
+    /// ```
+    /// Schedule.every_hours(6)  # Schedule('0 */6 * * *')
+    /// ```
+    #[staticmethod]
+    #[pyo3(text_signature = "(hours)")]
+    pub fn every_hours(hours: u32) -> Result<Self, ValidationError> {
+        check_step("hours", hours, 23)?;
+        Schedule::new(&format!("0 {} * * *", step_field(hours)))
+    }
+
+    /// Every day at a time of the cron timezone, or of the job's timezone
+    ///
+    /// # Arguments
+    /// * `at` - E.g. "03:00", "3am" or "noon", midnight by default
+    ///
+    /// # Example
+    /// ```
+    /// Schedule.daily(at="03:00")  # Schedule('0 3 * * *')
+    /// ```
+    #[staticmethod]
+    #[pyo3(signature = (at="00:00"), text_signature = "(at=\"00:00\")")]
+    pub fn daily(at: &str) -> Result<Self, ValidationError> {
+        let (hour, minute) = parse_at(at)?;
+        Schedule::new(&format!("{} {} * * *", minute, hour))
+    }
+
+    /// Every week on some days at a time
+    ///
+    /// # Arguments
+    /// * `days` - Days of the week, as names ("mon", "Monday") or numbers (0 or 7 is Sunday)
+    /// * `at` - E.g. "03:00", "3am" or "noon", midnight by default
+    ///
+    /// # Example
+    /// ```
+    /// Schedule.weekly(days=["mon", "fri"], at="9:30")  # Schedule('30 9 * * 1,5')
+    /// ```
+    #[staticmethod]
+    #[pyo3(signature = (days, at="00:00"), text_signature = "(days, at=\"00:00\")")]
+    pub fn weekly(days: Vec<Weekday>, at: &str) -> Result<Self, ValidationError> {
+        if days.is_empty() {
+            return Err(ValidationError::invalid_schedule(
+                "weekly needs at least one day".to_string(),
+            ));
+        }
+        let mut numbers = days
+            .iter()
+            .map(Weekday::number)
+            .collect::<Result<Vec<u32>, ValidationError>>()?;
+        numbers.sort_unstable();
+        numbers.dedup();
+        let numbers: Vec<String> = numbers.iter().map(u32::to_string).collect();
+        let (hour, minute) = parse_at(at)?;
+        Schedule::new(&format!("{} {} * * {}", minute, hour, numbers.join(",")))
+    }
+
+    /// Describe the schedule in plain English
+    pub fn describe(&self) -> Result<String, ValidationError> {
+        if is_hashed(&self.expression) {
+            return Err(format!(
+                "Schedule '{}' depends on the job name, use Job.describe_schedule()",
+                self.expression
+            )
+            .into());
+        }
+        describe_schedule(&self.expression)
+    }
+
+    #[getter(expression)]
+    fn py_expression(&self) -> String {
+        self.expression.clone()
+    }
+
+    pub fn __str__(&self) -> String {
+        self.expression.clone()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!("Schedule('{}')", self.expression)
+    }
+
+    fn __richcmp__(&self, other: &Self, op: CompareOp, py: Python) -> PyObject {
+        match op {
+            CompareOp::Eq => (self == other).into_py(py),
+            CompareOp::Ne => (self != other).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+}
+
+fn check_step(unit: &str, step: u32, max: u32) -> Result<(), ValidationError> {
+    if !(1..=max).contains(&step) {
+        return Err(ValidationError::invalid_schedule(format!(
+            "Every {} {} is out of range 1-{}",
+            step, unit, max
+        )));
+    }
+    Ok(())
+}
+
+/// The hour and minute of a time of day, e.g. "03:00", "3am" or "noon"
+fn parse_at(at: &str) -> Result<(u32, u32), ValidationError> {
+    parse_time(&at.trim().to_lowercase()).map_err(|e| ValidationError::invalid_schedule(e.into()))
+}
+
+/// `*` for a step of 1, `*/n` otherwise
+fn step_field(step: u32) -> String {
+    match step {
+        1 => "*".to_string(),
+        _ => format!("*/{}", step),
+    }
+}
+
+/// A day of the week given as a name or as a number, 0 and 7 are Sunday
+#[derive(Debug, Clone, FromPyObject)]
+pub enum Weekday {
+    Number(u32),
+    Name(String),
+}
+
+impl Weekday {
+    fn number(&self) -> Result<u32, ValidationError> {
+        match self {
+            Weekday::Number(day) if *day <= 7 => Ok(day % 7),
+            Weekday::Number(day) => Err(ValidationError::invalid_schedule(format!(
+                "Day of week {} is out of range 0-7",
+                day
+            ))),
+            Weekday::Name(name) => parse_weekday(&name.trim().to_lowercase()).ok_or_else(|| {
+                ValidationError::invalid_schedule(format!("Unknown day of week {}", name))
+            }),
+        }
+    }
+}
+
+impl From<&str> for Weekday {
+    fn from(name: &str) -> Self {
+        Weekday::Name(name.to_string())
+    }
+}
+
+/// A schedule given as a `Schedule` or as a string, which `Job` accepts alike
+#[derive(Debug, Clone, FromPyObject)]
+pub enum ScheduleArg {
+    Schedule(Schedule),
+    Text(String),
+}
+
+impl ScheduleArg {
+    pub fn into_expression(self) -> String {
+        match self {
+            ScheduleArg::Schedule(schedule) => schedule.expression,
+            ScheduleArg::Text(text) => normalize_schedule(&text),
+        }
+    }
+}
+
+impl From<&str> for ScheduleArg {
+    fn from(text: &str) -> Self {
+        ScheduleArg::Text(text.to_string())
+    }
+}
+
+impl From<String> for ScheduleArg {
+    fn from(text: String) -> Self {
+        ScheduleArg::Text(text)
+    }
+}
+
+impl From<Schedule> for ScheduleArg {
+    fn from(schedule: Schedule) -> Self {
+        ScheduleArg::Schedule(schedule)
+    }
+}
+
+/// The schedule as it is stored, intervals are spelled `<n> seconds` so the same interval
+/// always compares equal, other schedules are kept as they are
+pub fn normalize_schedule(schedule: &str) -> String {
+    match parse_schedule(schedule) {
+        Ok(ParsedSchedule::Interval(1)) => "1 second".to_string(),
+        Ok(ParsedSchedule::Interval(seconds)) => format!("{} seconds", seconds),
+        _ => schedule.to_string(),
+    }
+}
+
+/// The next `n` times a schedule fires, strictly after `after`
+/// Fewer are returned if the schedule stops firing, e.g. `@reboot`
+///
+/// # Arguments
+/// * `anchor` - Last run, interval schedules are counted from it
+/// * `tz` - Timezone the schedule is written in
+pub fn next_runs(
+    schedule: &str,
+    after: DateTime<Utc>,
+    n: usize,
+    anchor: Option<DateTime<Utc>>,
+    tz: Tz,
+) -> Result<Vec<DateTime<Utc>>, ValidationError> {
+    let parsed = parse_schedule(schedule)?;
+    let mut runs = Vec::with_capacity(n);
+    let mut last = after;
+    while runs.len() < n {
+        let Some(next) = parsed.next_after(last, anchor, tz) else {
+            break;
+        };
+        runs.push(next);
+        last = next;
+    }
+    Ok(runs)
+}
+
+/// The first `limit` times a schedule fires in `[start, end)`
+///
+/// # Arguments
+/// * `anchor` - Last run, interval schedules are counted from it
+/// * `tz` - Timezone the schedule is written in
+pub fn runs_between(
+    schedule: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    anchor: Option<DateTime<Utc>>,
+    tz: Tz,
+    limit: usize,
+) -> Result<Vec<DateTime<Utc>>, ValidationError> {
+    let parsed = parse_schedule(schedule)?;
+    // Without a last run, intervals are counted from the start of the window
+    let anchor = anchor.or(Some(start));
+    let mut runs = Vec::new();
+    // Runs are strictly after the given time, so start one microsecond early to include `start`
+    let mut last = start - Duration::microseconds(1);
+    while runs.len() < limit {
+        let Some(next) = parsed.next_after(last, anchor, tz) else {
+            break;
+        };
+        if next >= end {
+            break;
+        }
+        runs.push(next);
+        last = next;
+    }
+    Ok(runs)
+}
+
+/// The first `limit` runs of the active jobs in `[start, end)`, sorted by time then job name
+/// Jobs with a schedule or timezone that can't be computed are left out
+///
+/// # Arguments
+/// * `server` - The server's `cron.timezone`, jobs without a timezone run in it
+/// * `limit` - Maximum number of runs, no job computes more than that
+pub fn upcoming(
+    jobs: &[Job],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    server: Tz,
+    limit: usize,
+) -> Vec<(DateTime<Utc>, Job)> {
+    let mut runs: Vec<(DateTime<Utc>, Job)> = jobs
+        .iter()
+        .filter(|job| job.active)
+        .flat_map(|job| {
+            job.tz_or(server)
+                .and_then(|tz| {
+                    let schedule = job.expanded_schedule()?;
+                    runs_between(&schedule, start, end, job.last_run, tz, limit)
+                })
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |time| (time, job.clone()))
+        })
+        .collect();
+    runs.sort_by(|(a, job_a), (b, job_b)| a.cmp(b).then_with(|| job_a.name.cmp(&job_b.name)));
+    runs.truncate(limit);
+    runs
+}
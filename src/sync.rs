@@ -0,0 +1,524 @@
+//! Sync plan
+//! Compares the jobs stored in the pgcronner table with the ones in `cron.job`
+//! and decides what has to change, so that unchanged jobs keep their jobid.
+
+use crate::client::{Client, Transaction};
+use crate::errors::{DbError, ErrorKind, ValidationError};
+use crate::job::Job;
+use crate::report::{JobStatus, SyncReport};
+use crate::utils::{
+    alter_job, called_procedure, create_stored_procedure, drop_stored_procedure, pg_cron_version,
+    schedule_job, set_job_active, unschedule_job, version_at_least,
+};
+use log::{debug, warn};
+use pyo3::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A row of the `cron.job` table managed by pgcronner
+#[derive(Debug, Clone)]
+pub struct CronEntry {
+    pub jobid: i64,
+    pub jobname: String,
+    pub schedule: String,
+    pub command: String,
+    pub active: bool,
+}
+
+/// What sync does with jobs whose `active` column is false
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InactivePolicy {
+    /// Keep the job in `cron.job` with `active = false`
+    Disable,
+    /// Remove the job from `cron.job`
+    Unschedule,
+}
+
+impl TryFrom<&str> for InactivePolicy {
+    type Error = ValidationError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "disable" => Ok(InactivePolicy::Disable),
+            "unschedule" => Ok(InactivePolicy::Unschedule),
+            _ => Err(format!(
+                "Invalid inactive policy {}, expected 'disable' or 'unschedule'",
+                value
+            )
+            .into()),
+        }
+    }
+}
+
+/// A single step of a sync
+#[derive(Debug, Clone)]
+pub enum SyncAction {
+    /// Job is not in `cron.job` yet
+    Create(Job),
+    /// Job is in `cron.job` but its schedule changed
+    UpdateSchedule { before: CronEntry, after: Job },
+    /// Job is in `cron.job` but its command changed
+    UpdateCommand { before: CronEntry, after: Job },
+    /// The source of the job's stored procedure changed or is unknown
+    RecreateProcedure {
+        name: String,
+        before: Option<String>,
+        after: Job,
+    },
+    /// Job is inactive and has to be kept with `active = false`
+    Disable(Job),
+    /// Job is active again
+    Enable(Job),
+    /// Job is in `cron.job` but not in the table anymore, or inactive
+    Unschedule(CronEntry),
+    /// No job calls the stored procedure anymore
+    DropProcedure {
+        name: String,
+        before: Option<String>,
+    },
+    /// Job is already up to date
+    Keep(Job),
+}
+
+impl SyncAction {
+    /// Name of the job this action belongs to
+    pub fn jobname(&self) -> &str {
+        match self {
+            SyncAction::Create(job)
+            | SyncAction::Disable(job)
+            | SyncAction::Enable(job)
+            | SyncAction::Keep(job) => &job.name,
+            SyncAction::UpdateSchedule { after, .. }
+            | SyncAction::UpdateCommand { after, .. }
+            | SyncAction::RecreateProcedure { after, .. } => &after.name,
+            SyncAction::Unschedule(entry) => &entry.jobname,
+            SyncAction::DropProcedure { name, .. } => name,
+        }
+    }
+
+    /// Describe the action for a plan, `None` if there is nothing to do
+    fn describe(&self) -> Option<PlanAction> {
+        let (kind, before, after) = match self {
+            SyncAction::Create(job) => (
+                "create",
+                None,
+                Some(format!("{} {}", job.schedule, job.command)),
+            ),
+            SyncAction::UpdateSchedule { before, after } => (
+                "update_schedule",
+                Some(before.schedule.clone()),
+                Some(after.schedule.clone()),
+            ),
+            SyncAction::UpdateCommand { before, after } => (
+                "update_command",
+                Some(before.command.clone()),
+                Some(after.command.clone()),
+            ),
+            SyncAction::RecreateProcedure {
+                name,
+                before,
+                after,
+            } => (
+                "recreate_procedure",
+                before.clone().map(|source| format!("{}: {}", name, source)),
+                Some(format!("{}: {}", name, after.source)),
+            ),
+            SyncAction::Disable(_) => ("disable", Some("active".into()), Some("inactive".into())),
+            SyncAction::Enable(_) => ("enable", Some("inactive".into()), Some("active".into())),
+            SyncAction::Unschedule(entry) => (
+                "unschedule",
+                Some(format!("{} {}", entry.schedule, entry.command)),
+                None,
+            ),
+            SyncAction::DropProcedure { name, before } => (
+                "drop_procedure",
+                before.clone().map(|source| format!("{}: {}", name, source)),
+                None,
+            ),
+            SyncAction::Keep(_) => return None,
+        };
+
+        Some(PlanAction {
+            kind: kind.to_string(),
+            job: self.jobname().to_string(),
+            before,
+            after,
+        })
+    }
+}
+
+/// A change sync would make
+///
+/// # Arguments
+/// * `kind` - One of create, update_schedule, update_command, recreate_procedure, disable,
+///   enable, unschedule, drop_procedure
+/// * `job` - Name of the job
+/// * `before` - Value before the sync, if any
+/// * `after` - Value after the sync, if any
+///
+#[derive(Debug, Clone)]
+#[pyclass(module = "pgcronner")]
+pub struct PlanAction {
+    #[pyo3(get)]
+    pub kind: String,
+    #[pyo3(get)]
+    pub job: String,
+    #[pyo3(get)]
+    pub before: Option<String>,
+    #[pyo3(get)]
+    pub after: Option<String>,
+}
+
+#[pymethods]
+impl PlanAction {
+    pub fn __str__(&self) -> PyResult<String> {
+        Ok(self.to_string())
+    }
+
+    pub fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "PlanAction(kind={}, job={}, before={:?}, after={:?})",
+            self.kind, self.job, self.before, self.after
+        ))
+    }
+}
+
+impl fmt::Display for PlanAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let marker = match self.kind.as_str() {
+            "create" => '+',
+            "unschedule" | "drop_procedure" => '-',
+            _ => '~',
+        };
+        writeln!(f, "{} {} ({})", marker, self.job, self.kind)?;
+        if let Some(before) = &self.before {
+            for line in before.lines() {
+                writeln!(f, "-   {}", line)?;
+            }
+        }
+        if let Some(after) = &self.after {
+            for line in after.lines() {
+                writeln!(f, "+   {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The actions a sync would take, nothing is changed by computing it
+#[derive(Debug, Clone)]
+#[pyclass(module = "pgcronner")]
+pub struct SyncPlan {
+    #[pyo3(get)]
+    pub actions: Vec<PlanAction>,
+}
+
+impl SyncPlan {
+    pub fn new(actions: &[SyncAction]) -> Self {
+        Self {
+            actions: actions.iter().filter_map(SyncAction::describe).collect(),
+        }
+    }
+}
+
+#[pymethods]
+impl SyncPlan {
+    /// True if sync would not change anything
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn __str__(&self) -> PyResult<String> {
+        Ok(self.to_string())
+    }
+
+    pub fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("SyncPlan(actions={})", self.actions.len()))
+    }
+}
+
+impl fmt::Display for SyncPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.actions.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for action in &self.actions {
+            write!(f, "{}", action)?;
+        }
+        Ok(())
+    }
+}
+
+/// pg_cron runs interval schedules, e.g. '30 seconds', from this version on
+pub const INTERVAL_MIN_VERSION: (u32, u32) = (1, 5);
+
+/// Fail if an active job has an interval schedule and the installed pg_cron is too old to run it
+pub async fn check_interval_support(client: &mut impl Client, jobs: &[Job]) -> Result<(), DbError> {
+    let Some(job) = jobs
+        .iter()
+        .find(|job| job.active && job.has_interval_schedule())
+    else {
+        return Ok(());
+    };
+
+    let (major, minor) = INTERVAL_MIN_VERSION;
+    let installed = match pg_cron_version(client).await? {
+        Some(version) if version_at_least(&version, major, minor) => return Ok(()),
+        Some(version) => format!("installed version is {}", version),
+        None => "pg_cron is not installed".to_string(),
+    };
+    Err(DbError::new(format!(
+        "Job {} has interval schedule '{}', which needs pg_cron {}.{} or newer, {}",
+        job.name, job.schedule, major, minor, installed
+    ))
+    .with_kind(ErrorKind::PgCronNotInstalled)
+    .with_job(&job.name))
+}
+
+/// Compute the actions needed to bring `cron.job` in line with the table
+///
+/// # Arguments
+/// * `jobs` - Valid jobs read from the pgcronner table
+/// * `scheduled` - pgcronner jobs currently in `cron.job`
+/// * `procedures` - pgcronner stored procedures and the source they were created with,
+///   the ones no job calls anymore are dropped
+/// * `policy` - What to do with inactive jobs
+/// * `keep` - Names of rows that couldn't be read, their `cron.job` entries and procedures
+///   are left as they are
+pub fn compute_plan(
+    jobs: &[Job],
+    scheduled: &[CronEntry],
+    procedures: &HashMap<String, Option<String>>,
+    policy: InactivePolicy,
+    keep: &HashSet<String>,
+) -> Vec<SyncAction> {
+    let mut existing: HashMap<&str, &CronEntry> = scheduled
+        .iter()
+        .map(|entry| (entry.jobname.as_str(), entry))
+        .collect();
+
+    let mut actions = Vec::new();
+
+    // Inactive jobs are left out so they get unscheduled with the stale ones
+    let wanted = jobs
+        .iter()
+        .filter(|job| job.active || policy == InactivePolicy::Disable);
+
+    for job in wanted {
+        let entry = match existing.remove(job.name.as_str()) {
+            Some(entry) => entry,
+            None => {
+                actions.push(SyncAction::Create(job.clone()));
+                if !job.active {
+                    actions.push(SyncAction::Disable(job.clone()));
+                }
+                continue;
+            }
+        };
+        let before = actions.len();
+
+        // The procedure has to be up to date before the command switches to it
+        if let Some(name) = called_procedure(&job.command, &job.name) {
+            let source = procedures.get(&name).cloned().flatten();
+            if source.as_deref() != Some(job.source.as_str()) {
+                actions.push(SyncAction::RecreateProcedure {
+                    name,
+                    before: source,
+                    after: job.clone(),
+                });
+            }
+        }
+        if entry.schedule != job.schedule {
+            actions.push(SyncAction::UpdateSchedule {
+                before: entry.clone(),
+                after: job.clone(),
+            });
+        }
+        if entry.command != job.command {
+            actions.push(SyncAction::UpdateCommand {
+                before: entry.clone(),
+                after: job.clone(),
+            });
+        }
+        match (entry.active, job.active) {
+            (true, false) => actions.push(SyncAction::Disable(job.clone())),
+            (false, true) => actions.push(SyncAction::Enable(job.clone())),
+            _ => {}
+        }
+        if actions.len() == before {
+            actions.push(SyncAction::Keep(job.clone()));
+        }
+    }
+
+    // A row that is still in the table but invalid keeps its last good schedule
+    let kept: Vec<&CronEntry> = keep
+        .iter()
+        .filter_map(|name| existing.remove(name.as_str()))
+        .collect();
+
+    // Whatever is left in cron.job has no matching row anymore
+    let mut stale: Vec<&CronEntry> = existing.into_values().collect();
+    stale.sort_by_key(|entry| entry.jobid);
+    actions.extend(stale.into_iter().cloned().map(SyncAction::Unschedule));
+
+    // Procedures are owned by the jobs calling them, inactive jobs included
+    let referenced: HashSet<String> = jobs
+        .iter()
+        .filter_map(|job| called_procedure(&job.command, &job.name))
+        .chain(
+            kept.iter()
+                .filter_map(|entry| called_procedure(&entry.command, &entry.jobname)),
+        )
+        .collect();
+    let mut unused: Vec<(&String, &Option<String>)> = procedures
+        .iter()
+        .filter(|(name, _)| !referenced.contains(*name))
+        .collect();
+    unused.sort();
+    actions.extend(
+        unused
+            .into_iter()
+            .map(|(name, before)| SyncAction::DropProcedure {
+                name: name.clone(),
+                before: before.clone(),
+            }),
+    );
+
+    actions
+}
+
+async fn ensure_stored_procedure(client: &mut impl Client, job: &Job) -> Result<(), DbError> {
+    let Some(stored_procedure) = called_procedure(&job.command, &job.name) else {
+        return Ok(());
+    };
+    debug!("Creating stored procedure for job: {}", job.name);
+    create_stored_procedure(client, &stored_procedure, &job.source).await
+}
+
+/// Apply a single action, on failure the status tells which step failed
+pub async fn apply_action(
+    client: &mut impl Client,
+    action: &SyncAction,
+) -> Result<JobStatus, (JobStatus, DbError)> {
+    let procedure_failed = |e| (JobStatus::ProcedureFailed, e);
+    let schedule_failed = |e| (JobStatus::ScheduleFailed, e);
+
+    match action {
+        SyncAction::Create(job) => {
+            ensure_stored_procedure(client, job)
+                .await
+                .map_err(procedure_failed)?;
+            schedule_job(client, job).await.map_err(schedule_failed)?;
+            Ok(JobStatus::Scheduled)
+        }
+        SyncAction::UpdateSchedule { before, after } => {
+            alter_job(client, before.jobid, Some(&after.schedule), None)
+                .await
+                .map_err(schedule_failed)?;
+            Ok(JobStatus::Updated)
+        }
+        SyncAction::UpdateCommand { before, after } => {
+            alter_job(client, before.jobid, None, Some(&after.command))
+                .await
+                .map_err(schedule_failed)?;
+            Ok(JobStatus::Updated)
+        }
+        SyncAction::RecreateProcedure { after, .. } => {
+            ensure_stored_procedure(client, after)
+                .await
+                .map_err(procedure_failed)?;
+            Ok(JobStatus::Updated)
+        }
+        SyncAction::Disable(job) => {
+            set_job_active(client, &job.name, false)
+                .await
+                .map_err(schedule_failed)?;
+            Ok(JobStatus::Disabled)
+        }
+        SyncAction::Enable(job) => {
+            set_job_active(client, &job.name, true)
+                .await
+                .map_err(schedule_failed)?;
+            Ok(JobStatus::Updated)
+        }
+        SyncAction::Unschedule(entry) => {
+            unschedule_job(client, &entry.jobname)
+                .await
+                .map_err(schedule_failed)?;
+            Ok(JobStatus::Unscheduled)
+        }
+        SyncAction::DropProcedure { name, .. } => {
+            drop_stored_procedure(client, name)
+                .await
+                .map_err(procedure_failed)?;
+            Ok(JobStatus::ProcedureDropped)
+        }
+        SyncAction::Keep(_) => Ok(JobStatus::Unchanged),
+    }
+}
+
+/// Apply an action in a savepoint, so a failure doesn't abort the surrounding transaction
+async fn apply_in_savepoint(
+    transaction: &mut impl Client,
+    action: &SyncAction,
+) -> Result<JobStatus, (JobStatus, DbError)> {
+    let mut savepoint = transaction.transaction().await.map_err(|e| {
+        (
+            JobStatus::ScheduleFailed,
+            DbError::postgres("Could not create savepoint", &e),
+        )
+    })?;
+    let status = apply_action(&mut savepoint, action).await?;
+    savepoint.commit().await.map_err(|e| {
+        (
+            JobStatus::ScheduleFailed,
+            DbError::postgres("Could not release savepoint", &e),
+        )
+    })?;
+    Ok(status)
+}
+
+/// Apply all actions inside a transaction and record the outcome in the report
+///
+/// # Arguments
+/// * `transaction` - The transaction to run in, committing it is up to the caller
+/// * `actions` - The actions computed by `compute_plan`
+/// * `atomic` - Fail on the first action that fails, so the caller can roll back everything.
+///   Otherwise failed actions are skipped and the rest is applied.
+/// * `report` - Report to record the outcome of every job in
+pub async fn apply_plan(
+    transaction: &mut impl Client,
+    actions: &[SyncAction],
+    atomic: bool,
+    report: &mut SyncReport,
+) -> Result<(), DbError> {
+    for action in actions {
+        let result = match atomic {
+            true => apply_action(transaction, action).await,
+            false => apply_in_savepoint(transaction, action).await,
+        };
+        match result {
+            Ok(status) => {
+                debug!("Applied {:?} for job: {}", action, action.jobname());
+                report.record(action.jobname(), status, None);
+            }
+            Err((_, e)) if atomic => {
+                let context = format!("Could not sync job {}", action.jobname());
+                return Err(e.context(&context).with_job(action.jobname()));
+            }
+            Err((status, e)) => {
+                warn!(
+                    "Could not sync job {}, skipping: {}",
+                    action.jobname(),
+                    e.as_ref()
+                );
+                report.record(action.jobname(), status, Some(e.into()));
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,201 @@
+//! Timezones
+//! pg_cron reads schedules as times of `cron.timezone`. Jobs can be written in another timezone,
+//! their schedule is then translated to the server's when that gives exactly the same runs.
+//! When the offset between the two zones changes with daylight saving time, only schedules that
+//! don't depend on the hour can be translated.
+
+use chrono::{DateTime, Duration, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::collections::BTreeSet;
+
+use crate::client::Client;
+use crate::errors::{DbError, ValidationError};
+use crate::schedule::{is_full, parse_schedule, CronFields, ParsedSchedule, MACROS};
+
+/// Timezone pg_cron uses when `cron.timezone` is not set
+pub const DEFAULT_CRON_TIMEZONE: &str = "GMT";
+
+/// Offsets between zones are compared over this many hours, two years covers every DST change
+const OFFSET_HOURS: i64 = 2 * 366 * 24;
+
+pub fn parse_timezone(name: &str) -> Result<Tz, ValidationError> {
+    name.parse::<Tz>()
+        .map_err(|_| format!("Unknown timezone {}", name).into())
+}
+
+/// The timezone pg_cron reads schedules in
+pub async fn server_timezone(client: &mut impl Client) -> Result<Tz, DbError> {
+    let row = client
+        .query_one("SELECT current_setting('cron.timezone', true)", &[])
+        .await
+        .map_err(|e| DbError::postgres("Could not get cron.timezone", &e))?;
+    let name: Option<String> = row.get(0);
+    let name = name
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_CRON_TIMEZONE.to_string());
+    parse_timezone(&name).map_err(|e| DbError::new(e.into()))
+}
+
+/// Minutes `to` is ahead of `from`, every value it takes over two years from `start`
+fn offset_differences(from: Tz, to: Tz, start: DateTime<Utc>) -> BTreeSet<i32> {
+    let offset = |tz: Tz, time: DateTime<Utc>| {
+        tz.offset_from_utc_datetime(&time.naive_utc())
+            .fix()
+            .local_minus_utc()
+            / 60
+    };
+    (0..OFFSET_HOURS)
+        .map(|hour| start + Duration::hours(hour))
+        .map(|time| offset(to, time) - offset(from, time))
+        .collect()
+}
+
+fn format_offset(minutes: i32) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    format!("{}{}:{:02}", sign, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+/// A cron field listing the values of a bitset, `*` if it has all of them
+fn render_field(bits: u64, low: u32, high: u32) -> String {
+    if is_full(bits, low, high) {
+        return "*".to_string();
+    }
+    let mut ranges: Vec<String> = Vec::new();
+    let mut value = low;
+    while value <= high {
+        if bits & 1 << value == 0 {
+            value += 1;
+            continue;
+        }
+        let start = value;
+        while value < high && bits & 1 << (value + 1) != 0 {
+            value += 1;
+        }
+        ranges.push(match value - start {
+            0 => start.to_string(),
+            1 => format!("{},{}", start, value),
+            _ => format!("{}-{}", start, value),
+        });
+        value += 1;
+    }
+    ranges.join(",")
+}
+
+/// Shift the 5 fields of a schedule by `minutes`
+/// Fields that don't change are kept as they were written
+fn shift_fields(texts: &[&str], fields: &CronFields, minutes: i32) -> Result<String, String> {
+    if minutes == 0 {
+        return Ok(texts.join(" "));
+    }
+
+    let mut times = BTreeSet::new();
+    let mut day_shifts = BTreeSet::new();
+    for hour in (0..24).filter(|hour| fields.hours & 1 << hour != 0) {
+        for minute in (0..60).filter(|minute| fields.minutes & 1 << minute != 0) {
+            let time = hour * 60 + minute + minutes;
+            day_shifts.insert(time.div_euclid(24 * 60));
+            let time = time.rem_euclid(24 * 60);
+            times.insert((time / 60, time % 60));
+        }
+    }
+    let hours = times.iter().fold(0u64, |bits, (hour, _)| bits | 1 << hour);
+    let minutes_bits = times
+        .iter()
+        .fold(0u64, |bits, (_, minute)| bits | 1 << minute);
+    if times.len() != (hours.count_ones() * minutes_bits.count_ones()) as usize {
+        return Err("the shifted times can't be written as one schedule".to_string());
+    }
+
+    let mut texts: Vec<String> = texts.iter().map(|text| text.to_string()).collect();
+    if minutes_bits != fields.minutes {
+        texts[0] = render_field(minutes_bits, 0, 59);
+    }
+    if hours != fields.hours {
+        texts[1] = render_field(hours, 0, 23);
+    }
+
+    let any_day_of_month = is_full(fields.days, 1, 31) && is_full(fields.months, 1, 12);
+    let any_day = any_day_of_month && is_full(fields.weekdays, 0, 6);
+    if any_day {
+        return Ok(texts.join(" "));
+    }
+    let day_shift = match day_shifts.iter().collect::<Vec<_>>()[..] {
+        [shift] => *shift,
+        _ => return Err("some runs move to another day and others don't".to_string()),
+    };
+    if day_shift != 0 {
+        if !any_day_of_month {
+            return Err(
+                "runs move to another day, which can't be done for days of month or months"
+                    .to_string(),
+            );
+        }
+        let weekdays = (0..7)
+            .filter(|day| fields.weekdays & 1 << day != 0)
+            .fold(0u64, |bits, day: i32| {
+                bits | 1 << (day + day_shift).rem_euclid(7)
+            });
+        texts[4] = render_field(weekdays, 0, 6);
+    }
+    Ok(texts.join(" "))
+}
+
+/// Translate a schedule written in `from` to one giving exactly the same runs in `to`
+///
+/// # Arguments
+/// * `now` - The offsets between the zones are compared over two years from now
+///
+/// # Returns
+/// The translated schedule, or an error if no cron schedule gives the same runs,
+/// e.g. `0 3 * * *` from Europe/Berlin to GMT since Berlin is 1 or 2 hours ahead depending on DST
+pub fn translate_schedule(
+    schedule: &str,
+    from: Tz,
+    to: Tz,
+    now: DateTime<Utc>,
+) -> Result<String, ValidationError> {
+    if from == to {
+        return Ok(schedule.to_string());
+    }
+    let ParsedSchedule::Cron(fields) = parse_schedule(schedule)? else {
+        // Intervals and @reboot don't depend on the time of day
+        return Ok(schedule.to_string());
+    };
+    let expanded = MACROS
+        .iter()
+        .find(|(name, _)| *name == schedule.trim())
+        .map_or(schedule, |(_, expanded)| expanded);
+    let texts: Vec<&str> = expanded.split_whitespace().collect();
+
+    let cant_translate = |reason: String| -> ValidationError {
+        format!(
+            "Schedule '{}' in {} can't be translated exactly to cron.timezone {}: {}. Set cron.timezone to {} or write the schedule in {}",
+            schedule, from, to, reason, from, to
+        )
+        .into()
+    };
+
+    let differences = offset_differences(from, to, now);
+    let mut translated: Option<String> = None;
+    for minutes in &differences {
+        let shifted = shift_fields(&texts, &fields, *minutes).map_err(cant_translate)?;
+        match &translated {
+            Some(previous) if *previous != shifted => {
+                let offsets: BTreeSet<i32> = differences.iter().map(|minutes| -minutes).collect();
+                let offsets: Vec<String> = offsets.into_iter().map(format_offset).collect();
+                return Err(cant_translate(format!(
+                    "{} is {} from {} depending on daylight saving time",
+                    from,
+                    offsets.join(" or "),
+                    to
+                )));
+            }
+            _ => translated = Some(shifted),
+        }
+    }
+    // Keep the schedule as it was written, e.g. a macro, when nothing moved
+    match translated {
+        Some(translated) if translated != texts.join(" ") => Ok(translated),
+        _ => Ok(schedule.to_string()),
+    }
+}
@@ -0,0 +1,115 @@
+//! TLS
+//! Connections use TLS according to the `sslmode` of the database uri, like libpq:
+//! `disable`, `prefer` (default), `require`, `verify-ca` and `verify-full`.
+
+use crate::errors::{DbError, ValidationError};
+use openssl::ssl::{SslConnector, SslFiletype, SslMethod, SslVerifyMode};
+use postgres_openssl::MakeTlsConnector;
+use regex::Regex;
+
+/// How much the server is trusted, see the libpq docs for `sslmode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SslMode {
+    Disable,
+    Prefer,
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+impl TryFrom<&str> for SslMode {
+    type Error = ValidationError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "disable" => Ok(SslMode::Disable),
+            "prefer" => Ok(SslMode::Prefer),
+            "require" => Ok(SslMode::Require),
+            "verify-ca" => Ok(SslMode::VerifyCa),
+            "verify-full" => Ok(SslMode::VerifyFull),
+            _ => Err(format!(
+                "Invalid sslmode {}, use disable, prefer, require, verify-ca or verify-full",
+                value
+            )
+            .into()),
+        }
+    }
+}
+
+/// Certificates used for TLS connections
+///
+/// # Arguments
+/// * `root_cert` - CA bundle the server certificate is checked against, the system one if not set
+/// * `cert` - Client certificate
+/// * `key` - Private key of the client certificate
+///
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    pub root_cert: Option<String>,
+    pub cert: Option<String>,
+    pub key: Option<String>,
+}
+
+/// Read the sslmode of a uri, in url or key=value form
+///
+/// # Returns
+/// The sslmode and the uri with verify-ca/verify-full replaced by require,
+/// which is what the postgres crate understands, verification is done by the connector
+pub fn parse_sslmode(uri: &str) -> Result<(SslMode, String), ValidationError> {
+    let re = Regex::new(r"(^|[?&\s])sslmode=([\w-]*)").unwrap();
+    let Some(caps) = re.captures(uri) else {
+        return Ok((SslMode::Prefer, uri.to_string()));
+    };
+    let value = caps.get(2).unwrap();
+    let mode = SslMode::try_from(value.as_str())?;
+    let uri = match mode {
+        SslMode::VerifyCa | SslMode::VerifyFull => {
+            format!("{}require{}", &uri[..value.start()], &uri[value.end()..])
+        }
+        _ => uri.to_string(),
+    };
+    Ok((mode, uri))
+}
+
+/// Connector checking the server certificate as much as the sslmode asks for
+pub fn make_tls_connector(
+    mode: SslMode,
+    options: &TlsOptions,
+) -> Result<MakeTlsConnector, DbError> {
+    let tls_error =
+        |e: openssl::error::ErrorStack| DbError::new(format!("Could not set up TLS: {}", e));
+
+    let mut builder = SslConnector::builder(SslMethod::tls()).map_err(tls_error)?;
+    if let Some(root_cert) = &options.root_cert {
+        builder.set_ca_file(root_cert).map_err(tls_error)?;
+    }
+    if let Some(cert) = &options.cert {
+        builder
+            .set_certificate_chain_file(cert)
+            .map_err(tls_error)?;
+    }
+    if let Some(key) = &options.key {
+        builder
+            .set_private_key_file(key, SslFiletype::PEM)
+            .map_err(tls_error)?;
+    }
+
+    // Like libpq, require only checks the certificate when a CA bundle is given
+    let verify = match mode {
+        SslMode::VerifyCa | SslMode::VerifyFull => true,
+        SslMode::Require => options.root_cert.is_some(),
+        SslMode::Disable | SslMode::Prefer => false,
+    };
+    if !verify {
+        builder.set_verify(SslVerifyMode::NONE);
+    }
+
+    let mut connector = MakeTlsConnector::new(builder.build());
+    if mode != SslMode::VerifyFull {
+        connector.set_callback(|config, _| {
+            config.set_verify_hostname(false);
+            Ok(())
+        });
+    }
+    Ok(connector)
+}
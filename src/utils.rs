@@ -1,11 +1,16 @@
-use crate::errors::DbError;
+use crate::client::Client;
+use crate::errors::{DbError, ValidationError};
 use crate::job::Job;
+use crate::namespace::Namespace;
+use crate::sync::CronEntry;
+use crate::timezone::parse_timezone;
 
-use chrono::DateTime;
-use chrono::Utc;
+use chrono::{DateTime, Offset, Utc};
 use log::debug;
-use postgres::Client;
+use pyo3::prelude::*;
+use pyo3::types::{timezone_utc, PyDateTime, PyDelta, PyDeltaAccess};
 use regex::Regex;
+use std::collections::HashMap;
 
 const DEFAULT_TABLE_NAME: &str = "pgcronner_jobs";
 
@@ -24,104 +29,262 @@ pub fn get_stored_procedure_name(command: &str, default: &str) -> String {
     res.to_string()
 }
 
-pub fn create_stored_procedure(
-    client: &mut Client,
+/// Quote a string as a SQL literal, for statements that don't take bind parameters
+pub fn quote_literal(value: &str) -> String {
+    match value.contains('\\') {
+        true => format!("E'{}'", value.replace('\\', "\\\\").replace('\'', "''")),
+        false => format!("'{}'", value.replace('\'', "''")),
+    }
+}
+
+/// Quote a string as a SQL identifier
+pub fn quote_ident(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Name of the stored procedure a command calls, lowercased like postgres stores it
+pub fn called_procedure(command: &str, default: &str) -> Option<String> {
+    command
+        .contains("CALL")
+        .then(|| get_stored_procedure_name(command, default).to_lowercase())
+}
+
+pub async fn create_stored_procedure(
+    client: &mut impl Client,
     name: &str,
     source: &str,
 ) -> Result<(), DbError> {
-    match client.query(
-        &format!(
-            "CREATE OR REPLACE PROCEDURE {}() LANGUAGE SQL
+    // The source is kept as comment so sync can tell whether the procedure changed
+    // Neither statement takes bind parameters, the body is SQL on purpose
+    let name = quote_ident(name);
+    match client
+        .batch_execute(&format!(
+            "CREATE OR REPLACE PROCEDURE {name}() LANGUAGE SQL
             BEGIN ATOMIC
-                {}
-            END;",
-            name, source
-        ),
-        &[],
-    ) {
+                {source}
+            END;
+        COMMENT ON PROCEDURE {name}() IS {};",
+            quote_literal(source)
+        ))
+        .await
+    {
         Ok(_) => {
             debug!("Created stored procedure: {}", name);
             Ok(())
         }
-        Err(e) => Err(format!("Could not create stored procedure: {}", e).into()),
+        Err(e) => Err(DbError::postgres("Could not create stored procedure", &e)),
     }
 }
 
-pub fn schedule_job(client: &mut Client, job: &Job) -> Result<(), DbError> {
-    match client.query_one(
-        &format!(
-            "SELECT cron.schedule('{}', '{}', '{}')",
-            job.name, job.schedule, job.command,
-        ),
-        &[],
-    ) {
+pub async fn get_stored_procedures(
+    client: &mut impl Client,
+    namespace: &Namespace,
+) -> Result<HashMap<String, Option<String>>, DbError> {
+    let rows = client
+        .query(
+            "SELECT proname::text, obj_description(oid, 'pg_proc') FROM pg_catalog.pg_proc WHERE starts_with(proname, $1) AND prokind = 'p'",
+            &[&namespace.prefix()],
+        ).await
+        .map_err(|e| DbError::postgres("Could not fetch stored procedures", &e))?;
+
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+pub async fn schedule_job(client: &mut impl Client, job: &Job) -> Result<(), DbError> {
+    match client
+        .query_one(
+            "SELECT cron.schedule($1, $2, $3)",
+            &[&job.name, &job.schedule, &job.command],
+        )
+        .await
+    {
         Ok(_) => {
             debug!("Scheduled job: {}", job);
             Ok(())
         }
-        Err(e) => Err(format!("Could not schedule job: {}", e).into()),
+        Err(e) => Err(DbError::postgres("Could not schedule job", &e)),
     }
 }
 
-#[allow(dead_code)]
-pub fn unschedule_job(client: &mut Client, name: &str) -> Result<(), DbError> {
-    match client.query_one(&format!("SELECT cron.unschedule('{}')", name), &[]) {
+pub async fn unschedule_job(client: &mut impl Client, name: &str) -> Result<(), DbError> {
+    match client
+        .query_one("SELECT cron.unschedule($1)", &[&name])
+        .await
+    {
         Ok(_) => {
             debug!("Unscheduled job: {}", name);
             Ok(())
         }
-        Err(e) => Err(format!("Could not unschedule job: {}", e).into()),
+        Err(e) => Err(DbError::postgres("Could not unschedule job", &e)),
     }
 }
 
-pub fn delete_all_jobs(client: &mut Client) -> Result<(), DbError> {
-    debug!("Deleting all jobs");
+pub async fn alter_job(
+    client: &mut impl Client,
+    jobid: i64,
+    schedule: Option<&str>,
+    command: Option<&str>,
+) -> Result<(), DbError> {
+    match client
+        .query(
+            "SELECT cron.alter_job(job_id := $1, schedule := $2, command := $3)",
+            &[&jobid, &schedule, &command],
+        )
+        .await
+    {
+        Ok(_) => {
+            debug!("Altered job: {}", jobid);
+            Ok(())
+        }
+        Err(e) => Err(DbError::postgres("Could not alter job", &e)),
+    }
+}
+
+pub async fn set_job_active(
+    client: &mut impl Client,
+    name: &str,
+    active: bool,
+) -> Result<(), DbError> {
     match client.query_opt(
-        &"DELETE FROM cron.job WHERE jobname LIKE 'pgcronner%'".to_string(),
-        &[],
-    ) {
+        "SELECT cron.alter_job(jobid, active := $2) FROM cron.job WHERE jobname = $1 AND username = current_user",
+        &[&name, &active],
+    ).await {
+        Ok(Some(_)) => {
+            debug!("Set job {} active: {}", name, active);
+            Ok(())
+        }
+        Ok(None) => Err(format!("Job {} is not scheduled", name).into()),
+        Err(e) => Err(DbError::postgres("Could not alter job", &e)),
+    }
+}
+
+pub async fn get_scheduled_jobs(
+    client: &mut impl Client,
+    namespace: &Namespace,
+) -> Result<Vec<CronEntry>, DbError> {
+    let rows = client
+        .query(
+            "SELECT jobid, jobname, schedule, command, active FROM cron.job WHERE starts_with(jobname, $1) AND username = current_user ORDER BY jobid",
+            &[&namespace.prefix()],
+        ).await
+        .map_err(|e| DbError::postgres("Could not fetch cronjobs from cron.job", &e))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| CronEntry {
+            jobid: row.get(0),
+            jobname: row.get(1),
+            schedule: row.get(2),
+            command: row.get(3),
+            active: row.get(4),
+        })
+        .collect())
+}
+
+pub async fn delete_all_jobs(
+    client: &mut impl Client,
+    namespace: &Namespace,
+) -> Result<(), DbError> {
+    debug!("Deleting all jobs in namespace {}", namespace.prefix());
+    match client
+        .query_opt(
+            "DELETE FROM cron.job WHERE starts_with(jobname, $1) AND username = current_user",
+            &[&namespace.prefix()],
+        )
+        .await
+    {
         Ok(_) => {
             debug!("Deleted all jobs");
             Ok(())
         }
-        Err(e) => Err(format!("Could not fetch cronjobs from table: {e}").into()),
+        Err(e) => Err(DbError::postgres("Could not fetch cronjobs from table", &e)),
     }
 }
 
-pub fn delete_all_stored_procedures(client: &mut Client) -> Result<(), DbError> {
-    match client.query(
-        "
-            DO $$
-            DECLARE
-                func_name TEXT;
-            BEGIN
-                FOR func_name IN
-                    SELECT proname
-                    FROM pg_catalog.pg_proc
-                    WHERE proname LIKE 'pgcronner%' AND prokind = 'f'
-                LOOP
-                    EXECUTE 'DROP FUNCTION IF EXISTS ' || func_name || ' CASCADE';
-                END LOOP;
-            END $$;
-        ",
-        &[],
-    ) {
+pub async fn delete_all_stored_procedures(
+    client: &mut impl Client,
+    namespace: &Namespace,
+) -> Result<(), DbError> {
+    // regprocedure renders the name and argument types already quoted
+    let rows = client
+        .query(
+            "SELECT oid::regprocedure::text FROM pg_catalog.pg_proc WHERE starts_with(proname, $1) AND prokind = 'p'",
+            &[&namespace.prefix()],
+        ).await
+        .map_err(|e| DbError::postgres("Could not fetch stored procedures", &e))?;
+
+    for row in &rows {
+        let procedure: String = row.get(0);
+        client
+            .batch_execute(&format!("DROP PROCEDURE IF EXISTS {} CASCADE", procedure))
+            .await
+            .map_err(|e| DbError::postgres("Could not drop stored procedure", &e))?;
+    }
+    debug!("Deleted all stored procedures");
+    Ok(())
+}
+
+pub async fn drop_stored_procedure(client: &mut impl Client, name: &str) -> Result<(), DbError> {
+    match client
+        .batch_execute(&format!("DROP PROCEDURE IF EXISTS {}()", quote_ident(name)))
+        .await
+    {
         Ok(_) => {
-            debug!("Deleted all stored procedures");
+            debug!("Dropped stored procedure: {}", name);
             Ok(())
         }
-        Err(e) => Err(e.to_string().into()),
+        Err(e) => Err(DbError::postgres("Could not drop stored procedure", &e)),
     }
 }
 
-pub fn create_table(client: &mut Client, table_name: &str) -> Result<String, DbError> {
-    let table_name = match table_name.is_empty() {
-        true => DEFAULT_TABLE_NAME.to_string(),
-        false => table_name.trim().to_lowercase(),
+/// Name of the table, the default one if empty
+/// Names are kept as they are, mixed case included, and are quoted in every query
+pub fn table_name(schema: Option<&str>, table_name: &str) -> Result<String, ValidationError> {
+    let table_name = match table_name.trim() {
+        "" => DEFAULT_TABLE_NAME,
+        name => name,
     };
+    if schema.is_none() && table_name.contains('.') {
+        return Err(format!(
+            "Invalid table name {}, pass the schema with the schema argument",
+            table_name
+        )
+        .into());
+    }
+    Ok(table_name.to_string())
+}
+
+/// Quoted table name, qualified with the schema if there is one
+pub fn qualified_table(schema: Option<&str>, table_name: &str) -> String {
+    match schema {
+        Some(schema) => format!("{}.{}", quote_ident(schema), quote_ident(table_name)),
+        None => quote_ident(table_name),
+    }
+}
+
+/// Create the schema and the table if they don't exist
+///
+/// # Returns
+/// The quoted table name to use in queries
+pub async fn create_table(
+    client: &mut impl Client,
+    schema: Option<&str>,
+    table_name: &str,
+) -> Result<String, DbError> {
+    if let Some(schema) = schema {
+        client
+            .batch_execute(&format!(
+                "CREATE SCHEMA IF NOT EXISTS {}",
+                quote_ident(schema)
+            ))
+            .await
+            .map_err(|e| DbError::postgres("Could not create schema", &e))?;
+    }
+
+    let table_name = qualified_table(schema, table_name);
     let table = format!(
         "
-        CREATE TABLE IF NOT EXISTS {table_name} (
+        CREATE TABLE IF NOT EXISTS {} (
         id SERIAL PRIMARY KEY,
         name VARCHAR(255) NOT NULL UNIQUE,
         command TEXT NOT NULL,
@@ -129,37 +292,158 @@ pub fn create_table(client: &mut Client, table_name: &str) -> Result<String, DbE
         source TEXT,
         active BOOLEAN NOT NULL DEFAULT TRUE,
         last_run TIMESTAMPTZ,
-        created TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP)",
+        created TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        timezone TEXT);
+        ALTER TABLE {} ADD COLUMN IF NOT EXISTS timezone TEXT;",
+        table_name, table_name
     );
 
-    match client.query(&table, &[]) {
+    match client.batch_execute(&table).await {
         Ok(_) => Ok(table_name),
-        Err(e) => Err(format!("Could not create table: {}", e).into()),
+        Err(e) => Err(DbError::postgres("Could not create table", &e)),
     }
 }
 
-pub fn get_last_run(client: &mut Client, jobname: &str) -> Option<DateTime<Utc>> {
-    let q = client
-        .query(
+/// Index that makes the last run of a job an index lookup instead of a scan of the history
+pub const RUN_DETAILS_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS job_run_details_jobid_start_time_idx ON cron.job_run_details (jobid, start_time)";
+
+/// Query selecting jobs from the table together with their last run, in a single statement
+///
+/// # Arguments
+/// * `table` - Quoted table name
+/// * `condition` - WHERE clause, on the table aliased as `t`
+pub fn select_jobs(table: &str, condition: &str) -> String {
+    format!(
+        "
+        SELECT t.id, t.name, t.command, t.schedule, t.source, t.active, runs.last_run, t.timezone
+        FROM {table} t
+        LEFT JOIN LATERAL (
+            SELECT max(d.start_time) AS last_run
+            FROM cron.job j
+            JOIN cron.job_run_details d ON d.jobid = j.jobid
+            WHERE j.jobname = t.name AND j.username = current_user
+        ) runs ON true
+        WHERE {condition}
+        ORDER BY t.id"
+    )
+}
+
+/// Update the last_run column of every job in the namespace with a single statement
+///
+/// # Returns
+/// The number of jobs whose last_run changed
+pub async fn refresh_last_runs(
+    client: &mut impl Client,
+    table: &str,
+    namespace: &Namespace,
+) -> Result<u64, DbError> {
+    client
+        .execute(
             &format!(
                 "
-            WITH job AS (
-                SELECT jobid FROM cron.job WHERE jobname = '{}'
-            )
-            SELECT max(start_time) as last_run_time FROM cron.job_run_details WHERE jobid = (SELECT jobid FROM job)",
-                jobname
+                UPDATE {table} t SET last_run = runs.last_run
+                FROM (
+                    SELECT j.jobname, max(d.start_time) AS last_run
+                    FROM cron.job j
+                    JOIN cron.job_run_details d ON d.jobid = j.jobid
+                    WHERE starts_with(j.jobname, $1) AND j.username = current_user
+                    GROUP BY j.jobname
+                ) runs
+                WHERE t.name = runs.jobname AND t.last_run IS DISTINCT FROM runs.last_run"
             ),
+            &[&namespace.prefix()],
+        )
+        .await
+        .map_err(|e| DbError::postgres("Could not refresh last runs", &e))
+}
+
+/// Installed version of the pg_cron extension, None if it isn't installed
+pub async fn pg_cron_version(client: &mut impl Client) -> Result<Option<String>, DbError> {
+    let row = client
+        .query_opt(
+            "SELECT extversion FROM pg_catalog.pg_extension WHERE extname = 'pg_cron'",
             &[],
         )
-        .map_err(|e| DbError::new(format!("Could not fetch last run time: {e}")))
-        .expect("Could not fetch last run time");
+        .await
+        .map_err(|e| DbError::postgres("Could not get pg_cron version", &e))?;
+    Ok(row.map(|row| row.get(0)))
+}
 
-    debug!("Last run query: {:?}", q);
+/// True if an extension version such as "1.5" or "1.4-1" is at least `major.minor`
+pub fn version_at_least(version: &str, major: u32, minor: u32) -> bool {
+    let mut parts = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|part| part.parse::<u32>().unwrap_or(0));
+    let found = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+    found >= (major, minor)
+}
 
-    let last_run = q
-        .first()
-        .expect("Could not get last run time")
-        .get::<_, Option<DateTime<Utc>>>("last_run_time");
+/// True if `cron.job_run_details` has an index starting with (jobid, start_time)
+pub async fn has_run_details_index(client: &mut impl Client) -> Result<bool, DbError> {
+    let row = client
+        .query_one(
+            "
+            SELECT EXISTS (
+                SELECT 1
+                FROM pg_catalog.pg_index i
+                JOIN pg_catalog.pg_attribute a1 ON a1.attrelid = i.indrelid AND a1.attnum = i.indkey[0]
+                JOIN pg_catalog.pg_attribute a2 ON a2.attrelid = i.indrelid AND a2.attnum = i.indkey[1]
+                WHERE i.indrelid = to_regclass('cron.job_run_details')
+                    AND a1.attname = 'jobid' AND a2.attname = 'start_time'
+            )",
+            &[],
+        ).await
+        .map_err(|e| DbError::postgres("Could not check indexes", &e))?;
+    Ok(row.get(0))
+}
+
+/// Convert to an aware Python datetime in UTC
+pub fn to_py_datetime(
+    py: Python<'_>,
+    time: Option<DateTime<Utc>>,
+) -> PyResult<Option<&PyDateTime>> {
+    time.map(|time| {
+        PyDateTime::from_timestamp(
+            py,
+            time.timestamp_micros() as f64 / 1_000_000.0,
+            Some(timezone_utc(py)),
+        )
+    })
+    .transpose()
+}
+
+/// Convert to an aware Python datetime in a timezone, UTC if None
+/// The offset of the timezone at that time is computed here and given as a fixed offset
+/// `datetime.timezone`, named like `JST`, as `zoneinfo` only exists from Python 3.9
+pub fn to_py_datetime_in<'p>(
+    py: Python<'p>,
+    time: DateTime<Utc>,
+    timezone: Option<&str>,
+) -> PyResult<&'p PyDateTime> {
+    let timestamp = time.timestamp_micros() as f64 / 1_000_000.0;
+    let Some(timezone) = timezone else {
+        return PyDateTime::from_timestamp(py, timestamp, Some(timezone_utc(py)));
+    };
+    let local = time.with_timezone(&parse_timezone(timezone)?);
+    let offset = PyDelta::new(py, 0, local.offset().fix().local_minus_utc(), 0, true)?;
+    let tzinfo = py
+        .import("datetime")?
+        .getattr("timezone")?
+        .call1((offset, local.format("%Z").to_string()))?;
+    PyDateTime::from_timestamp(py, timestamp, Some(tzinfo.downcast()?))
+}
+
+/// Convert a Python datetime, naive ones are taken as local time like `datetime.timestamp()` does
+pub fn from_py_datetime(time: &PyDateTime) -> PyResult<DateTime<Utc>> {
+    let timestamp: f64 = time.call_method0("timestamp")?.extract()?;
+    DateTime::from_timestamp_micros((timestamp * 1_000_000.0).round() as i64)
+        .ok_or_else(|| ValidationError::new(format!("Invalid datetime {}", time)).into())
+}
 
-    last_run
+/// Convert a Python timedelta
+pub fn from_py_timedelta(delta: &PyDelta) -> chrono::Duration {
+    chrono::Duration::days(delta.get_days() as i64)
+        + chrono::Duration::seconds(delta.get_seconds() as i64)
+        + chrono::Duration::microseconds(delta.get_microseconds() as i64)
 }